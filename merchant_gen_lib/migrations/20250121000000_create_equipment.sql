@@ -0,0 +1,18 @@
+-- The equipment table from AoN, plus any imported homebrew
+-- `origin` is "aon" for bundled items and the import's source tag otherwise
+CREATE TABLE IF NOT EXISTS equipment(
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT,
+    pfs TEXT,
+    source TEXT,
+    rarity TEXT,
+    trait TEXT,
+    item_category TEXT,
+    item_subcategory TEXT,
+    level INTEGER,
+    price TEXT,
+    bulk TEXT,
+    usage TEXT,
+    spoilers TEXT,
+    origin TEXT NOT NULL DEFAULT 'aon'
+);
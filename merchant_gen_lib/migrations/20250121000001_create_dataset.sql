@@ -0,0 +1,6 @@
+-- Which version of the bundled csv the equipment table was seeded from
+CREATE TABLE IF NOT EXISTS dataset(
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    checksum TEXT NOT NULL,
+    seeded_at TEXT NOT NULL
+);
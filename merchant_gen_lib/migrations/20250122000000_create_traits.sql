@@ -0,0 +1,36 @@
+-- Traits split out of the comma separated trait column, so items can be
+-- filtered by trait without matching strings
+CREATE TABLE IF NOT EXISTS traits(
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE COLLATE NOCASE
+);
+
+CREATE TABLE IF NOT EXISTS item_traits(
+    item_id INTEGER NOT NULL REFERENCES equipment(id) ON DELETE CASCADE,
+    trait_id INTEGER NOT NULL REFERENCES traits(id),
+    PRIMARY KEY (item_id, trait_id)
+);
+
+CREATE INDEX IF NOT EXISTS item_traits_trait ON item_traits(trait_id);
+
+-- link the items already in the table
+CREATE TEMP TABLE split_traits AS
+WITH RECURSIVE split(item_id, name, rest) AS (
+    SELECT id, '', coalesce(trait, '') || ',' FROM equipment
+    UNION ALL
+    SELECT
+        item_id,
+        trim(substr(rest, 1, instr(rest, ',') - 1)),
+        substr(rest, instr(rest, ',') + 1)
+    FROM split
+    WHERE rest != ''
+)
+SELECT DISTINCT item_id, name FROM split WHERE name != '';
+
+INSERT OR IGNORE INTO traits(name) SELECT name FROM split_traits;
+
+INSERT OR IGNORE INTO item_traits(item_id, trait_id)
+SELECT split_traits.item_id, traits.id
+FROM split_traits JOIN traits ON traits.name = split_traits.name;
+
+DROP TABLE split_traits;
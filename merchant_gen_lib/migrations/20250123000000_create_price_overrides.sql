@@ -0,0 +1,26 @@
+-- Prices that fill in or correct the price column, keyed by item name
+CREATE TABLE IF NOT EXISTS price_overrides(
+    name TEXT PRIMARY KEY COLLATE NOCASE,
+    price TEXT NOT NULL,
+    origin TEXT NOT NULL DEFAULT 'aon'
+);
+
+-- Every item with overrides applied, which is what generation reads from
+CREATE VIEW IF NOT EXISTS priced_equipment AS
+SELECT
+    equipment.id,
+    equipment.name,
+    equipment.pfs,
+    equipment.source,
+    equipment.rarity,
+    equipment.trait,
+    equipment.item_category,
+    equipment.item_subcategory,
+    equipment.level,
+    coalesce(price_overrides.price, equipment.price) AS price,
+    equipment.bulk,
+    equipment.usage,
+    equipment.spoilers,
+    equipment.origin
+FROM equipment
+LEFT JOIN price_overrides ON price_overrides.name = equipment.name;
@@ -0,0 +1,76 @@
+/// What a merchant has left to spend while their shelves are filled
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Budget {
+    /// The most to spend in cp
+    target: i32,
+    /// Spent so far in cp
+    spent: i32,
+}
+
+impl Budget {
+    pub fn new(target: i32) -> Self {
+        Self {
+            target: target.max(0),
+            spent: 0,
+        }
+    }
+
+    pub fn target(&self) -> i32 {
+        self.target
+    }
+
+    pub fn spent(&self) -> i32 {
+        self.spent
+    }
+
+    pub fn remaining(&self) -> i32 {
+        (self.target - self.spent).max(0)
+    }
+
+    pub fn can_afford(&self, cp: i32) -> bool {
+        cp <= self.remaining()
+    }
+
+    pub fn spend(&mut self, cp: i32) {
+        self.spent += cp;
+    }
+}
+
+/// Why a merchant stopped stocking items
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum FillStop {
+    /// The budget was spent to within the profile's tolerance
+    TargetReached,
+    /// Money is left, but nothing the profile allows is cheap enough
+    NothingAffordable,
+    /// The profile's limit on attempts ran out before the budget was spent
+    AttemptsExhausted,
+}
+
+impl std::fmt::Display for FillStop {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FillStop::TargetReached => write!(f, "the budget was spent"),
+            FillStop::NothingAffordable => write!(f, "nothing left is affordable"),
+            FillStop::AttemptsExhausted => write!(f, "too many attempts to find an item"),
+        }
+    }
+}
+
+/// How filling a merchant's shelves went
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct FillSummary {
+    pub budget: Budget,
+    /// Items added, counting every item in a batch
+    pub items: u32,
+    /// Times an item was picked, including picks that found nothing
+    pub attempts: u32,
+    pub stop: FillStop,
+}
+
+impl FillSummary {
+    /// Whether the budget was spent as intended
+    pub fn is_complete(&self) -> bool {
+        self.stop == FillStop::TargetReached
+    }
+}
@@ -0,0 +1,247 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    ops::RangeInclusive,
+};
+
+use anyhow::Result;
+use sqlx::{Pool, Sqlite};
+
+use crate::{
+    database,
+    item::{Item, ItemCategory, Rarity},
+};
+
+/// Every item with a price, loaded from the database once and indexed for
+/// stocking shops
+///
+/// Generating a merchant only needs the items a profile can stock, so loading
+/// a catalog up front and sharing it between merchants avoids going back to
+/// the database for every item picked.
+#[derive(Debug, Default, Clone)]
+pub struct ItemCatalog {
+    groups: HashMap<(ItemCategory, Rarity), Group>,
+    /// Where the first item with each name is in `groups`
+    names: HashMap<String, ((ItemCategory, Rarity), usize)>,
+}
+
+/// The items of one category and rarity
+#[derive(Debug, Default, Clone)]
+struct Group {
+    /// Sorted by level then price
+    items: Vec<Item>,
+    /// The cheapest price in cp at each level, in level order
+    cheapest: Vec<(i32, i32)>,
+}
+
+impl Group {
+    /// `items` must already be sorted by level then price
+    fn new(items: Vec<Item>) -> Self {
+        let mut cheapest: Vec<(i32, i32)> = vec![];
+        for item in items.iter() {
+            if cheapest.last().map(|(level, _)| *level) != Some(item.level) {
+                cheapest.push((item.level, price_cp(item)));
+            }
+        }
+        Self { items, cheapest }
+    }
+
+    fn levels(&self, levels: &RangeInclusive<i32>) -> &[Item] {
+        let start = self.items.partition_point(|i| i.level < *levels.start());
+        let end = self.items.partition_point(|i| i.level <= *levels.end());
+        &self.items[start..end.max(start)]
+    }
+}
+
+impl ItemCatalog {
+    /// Load every priced item from the database
+    pub async fn load(pool: &Pool<Sqlite>) -> Result<Self> {
+        let mut items = vec![];
+        for rarity in enum_iterator::all::<Rarity>() {
+            items.extend(database::get_all(pool, rarity, true).await?);
+        }
+        Ok(Self::from_items(items))
+    }
+
+    /// A catalog of `items`, leaving out any without a price
+    pub fn from_items<I: IntoIterator<Item = Item>>(items: I) -> Self {
+        // names point at the first item with that name, like
+        // `database::get_by_name`
+        let mut first = HashMap::new();
+        let mut grouped: HashMap<(ItemCategory, Rarity), Vec<(usize, Item)>> = HashMap::new();
+        for (order, item) in items.into_iter().filter(|i| i.price.is_some()).enumerate() {
+            first.entry(item.name.clone()).or_insert(order);
+            grouped
+                .entry((item.item_category, item.rarity))
+                .or_default()
+                .push((order, item));
+        }
+
+        let mut groups = HashMap::new();
+        let mut names = HashMap::new();
+        for (key, mut items) in grouped {
+            // stable, so items of the same level and price keep their order
+            items.sort_by_key(|(_, i)| (i.level, price_cp(i)));
+            for (index, (order, item)) in items.iter().enumerate() {
+                if first.get(&item.name) == Some(order) {
+                    names.insert(item.name.clone(), (key, index));
+                }
+            }
+            groups.insert(key, Group::new(items.into_iter().map(|(_, i)| i).collect()));
+        }
+
+        Self { groups, names }
+    }
+
+    /// Items of `category` and `rarity` with a level in `levels`, sorted by
+    /// level then price
+    pub fn items(
+        &self,
+        category: ItemCategory,
+        rarity: Rarity,
+        levels: RangeInclusive<i32>,
+    ) -> &[Item] {
+        self.groups
+            .get(&(category, rarity))
+            .map(|group| group.levels(&levels))
+            .unwrap_or_default()
+    }
+
+    /// The price in cp of the cheapest item of `category` and `rarity` with a
+    /// level in `levels`
+    pub fn cheapest(
+        &self,
+        category: ItemCategory,
+        rarity: Rarity,
+        levels: RangeInclusive<i32>,
+    ) -> Option<i32> {
+        self.groups
+            .get(&(category, rarity))?
+            .cheapest
+            .iter()
+            .filter(|(level, _)| levels.contains(level))
+            .map(|(_, cp)| *cp)
+            .min()
+    }
+
+    /// The price of the cheapest common item in each category with a level in
+    /// `levels`
+    pub fn minimums(&self, levels: RangeInclusive<i32>) -> HashMap<ItemCategory, i32> {
+        enum_iterator::all::<ItemCategory>()
+            .filter_map(|category| {
+                let min = self.cheapest(category, Rarity::Common, levels.clone())?;
+                Some((category, min))
+            })
+            .collect()
+    }
+
+    /// The highest level of any item of `rarity`
+    pub fn max_level(&self, rarity: Rarity) -> Option<i32> {
+        self.groups
+            .iter()
+            .filter(|((_, r), _)| *r == rarity)
+            .filter_map(|(_, group)| group.items.last().map(|i| i.level))
+            .max()
+    }
+
+    /// Every category with at least one item
+    pub fn categories(&self) -> Vec<ItemCategory> {
+        enum_iterator::all::<ItemCategory>()
+            .filter(|c| self.groups.keys().any(|(category, _)| category == c))
+            .collect()
+    }
+
+    /// Every subcategory of `category`, with how many items are in each
+    /// Items without a subcategory are counted under ""
+    pub fn subcategories(&self, category: ItemCategory) -> BTreeMap<&str, usize> {
+        let mut out = BTreeMap::new();
+        for rarity in enum_iterator::all::<Rarity>() {
+            let Some(group) = self.groups.get(&(category, rarity)) else {
+                continue;
+            };
+            for item in group.items.iter() {
+                *out.entry(item.item_subcategory.as_str()).or_default() += 1;
+            }
+        }
+        out
+    }
+
+    /// The item with the given name
+    /// Name must match string exactly as it appears on AoN
+    pub fn get(&self, name: &str) -> Option<&Item> {
+        let (key, index) = self.names.get(name)?;
+        self.groups.get(key)?.items.get(*index)
+    }
+
+    /// The number of items in the catalog
+    pub fn len(&self) -> usize {
+        self.groups.values().map(|g| g.items.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+}
+
+fn price_cp(item: &Item) -> i32 {
+    item.price.as_ref().map_or(0, |p| p.as_cp())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        catalog::ItemCatalog,
+        database::{self, test_pool},
+        item::{Item, ItemCategory, Rarity},
+    };
+
+    #[tokio::test]
+    async fn matches_the_database() {
+        let pool = test_pool().await;
+        let catalog = ItemCatalog::load(&pool).await.unwrap();
+        assert!(!catalog.is_empty());
+
+        for category in [ItemCategory::Weapons, ItemCategory::AlchemicalItems] {
+            for rarity in [Rarity::Common, Rarity::Uncommon] {
+                let mut expected = database::get_category(&pool, category, rarity, 2..=6, true)
+                    .await
+                    .unwrap();
+                let items = catalog.items(category, rarity, 2..=6);
+                let order = |i: &Item| (i.level, i.price.as_ref().unwrap().as_cp());
+                assert!(items.windows(2).all(|w| order(&w[0]) <= order(&w[1])));
+
+                let mut items = items.to_vec();
+                items.sort();
+                expected.sort();
+                assert_eq!(items, expected);
+
+                let cheapest = expected
+                    .iter()
+                    .map(|i| i.price.as_ref().unwrap().as_cp())
+                    .min();
+                assert_eq!(catalog.cheapest(category, rarity, 2..=6), cheapest);
+            }
+        }
+        assert!(catalog
+            .items(ItemCategory::Weapons, Rarity::Common, 40..=50)
+            .is_empty());
+
+        let max_level = catalog.max_level(Rarity::Common).unwrap();
+        assert!(catalog
+            .items(
+                ItemCategory::Weapons,
+                Rarity::Common,
+                max_level + 1..=i32::MAX
+            )
+            .is_empty());
+        assert!(enum_iterator::all::<ItemCategory>().any(|c| !catalog
+            .items(c, Rarity::Common, max_level..=max_level)
+            .is_empty()));
+
+        let weapons = catalog.subcategories(ItemCategory::Weapons);
+        assert!(weapons["Base Weapons"] > weapons["Specific Magic Weapons"]);
+
+        let rations = database::get_by_name(&pool, "Rations").await.unwrap();
+        assert_eq!(catalog.get("Rations"), rations.as_ref());
+        assert!(catalog.get("rations").is_none());
+    }
+}
@@ -1,20 +1,41 @@
-use anyhow::{Context, Result};
-use sqlx::{sqlite::SqlitePoolOptions, Pool, Sqlite};
-use std::{collections::HashMap, fs::File, path::Path};
+use anyhow::{bail, Context, Result};
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqlitePoolOptions},
+    Pool, QueryBuilder, Sqlite, SqliteConnection,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+};
 
-use crate::item::{Item, ItemCategory, Rarity};
+use crate::{
+    catalog::ItemCatalog,
+    item::{parse_traits, Bulk, Item, ItemCategory, PfsStatus, Price, Rarity},
+};
 
-const DATABASE_PATH: &str = "./database.db";
-const CSV_PATH: &str = "assets/equipment.csv";
+/// Environment variable that overrides where the database is kept
+pub const DATABASE_ENV: &str = "MERCHANT_DB";
+const DATABASE_FILENAME: &str = "database.db";
+/// Origin of the items that come from the bundled csv
+pub const BUNDLED_ORIGIN: &str = "aon";
+/// The equipment table from AoN, built into the binary so installed copies
+/// don't depend on the source tree
+pub(crate) const EQUIPMENT_CSV: &str = include_str!("../assets/equipment.csv");
+/// Prices for bundled items AoN lists without one, keyed by item name
+const PRICE_OVERRIDES: &str = include_str!("../assets/price_overrides.toml");
+/// Origin of price overrides imported by the user
+pub const USER_ORIGIN: &str = "user";
 
 #[derive(sqlx::FromRow, Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct DbItem {
     pub name: String,
-    pub pfs: String,
+    pub pfs: PfsStatus,
     pub source: String,
-    pub rarity: String,
+    pub rarity: Rarity,
+    /// Comma separated, see `item::parse_traits`
     pub r#trait: String,
-    pub item_category: String,
+    pub item_category: ItemCategory,
     pub item_subcategory: String,
     pub level: i32,
     pub price: String,
@@ -23,98 +44,524 @@ pub struct DbItem {
     pub spoilers: String,
 }
 
+/// Filters, sorting and paging for `search`
+/// Any filter left as `None` matches every item
+#[derive(Debug, Default, Clone)]
+pub struct ItemQuery {
+    /// Case insensitive substring of the item's name
+    pub name: Option<String>,
+    pub min_level: Option<i32>,
+    pub max_level: Option<i32>,
+    pub rarity: Option<Rarity>,
+    pub category: Option<ItemCategory>,
+    /// Case insensitive, otherwise must match string exactly as it appears on AoN
+    pub subcategory: Option<String>,
+    /// The item must have this trait (case insensitive)
+    pub has_trait: Option<String>,
+    /// If not empty, the item must have at least one of these traits (case
+    /// insensitive)
+    pub any_traits: Vec<String>,
+    /// The item must have none of these traits (case insensitive)
+    pub excluded_traits: Vec<String>,
+    /// Lowest price in cp, items without a price never match a price bound
+    pub min_price: Option<i32>,
+    /// Highest price in cp, items without a price never match a price bound
+    pub max_price: Option<i32>,
+    pub bulk: Option<Bulk>,
+    /// Case insensitive substring of the item's source
+    pub source: Option<String>,
+    pub sort_by: SortBy,
+    pub descending: bool,
+    /// The most items to return, after skipping `offset` items
+    pub limit: Option<usize>,
+    pub offset: usize,
+}
+
+/// What to sort search results by
+/// Ties are always broken by name
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub enum SortBy {
+    #[default]
+    Name,
+    Level,
+    Price,
+}
+
+impl std::str::FromStr for SortBy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "name" => Ok(SortBy::Name),
+            "level" => Ok(SortBy::Level),
+            "price" => Ok(SortBy::Price),
+            _ => bail!(
+                "Unknown sort key: {} (expected one of: name, level, price)",
+                s
+            ),
+        }
+    }
+}
+
+/// One page of search results
+#[derive(Debug, Clone)]
+pub struct SearchResults {
+    pub items: Vec<Item>,
+    /// How many items matched before paging
+    pub total: usize,
+}
+
 #[derive(sqlx::FromRow, Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct CategoryColumn {
     pub item_category: String,
 }
 
+/// Where the database is kept unless told otherwise
+/// `$MERCHANT_DB` if it's set, otherwise the platform's data directory, e.g.
+/// `~/.local/share/merchant/database.db` on Linux
+pub fn default_db_path() -> PathBuf {
+    if let Some(path) = std::env::var_os(DATABASE_ENV).filter(|p| !p.is_empty()) {
+        return PathBuf::from(path);
+    }
+    match dirs::data_dir() {
+        Some(dir) => dir.join("merchant").join(DATABASE_FILENAME),
+        None => PathBuf::from(DATABASE_FILENAME),
+    }
+}
+
+/// Open the database at its default location, see `default_db_path`
 pub async fn init_db() -> Result<Pool<Sqlite>> {
-    let mut do_populate = false;
-    let pool = match Path::new(DATABASE_PATH).exists() {
-        true => {
-            tracing::debug!("Database found");
-            SqlitePoolOptions::new()
-                .max_connections(1)
-                .connect(&format!("sqlite://{}", DATABASE_PATH))
-                .await
-        }
-        false => {
-            tracing::debug!("Database not found");
-            do_populate = true;
-            let _ = File::create(DATABASE_PATH);
-            SqlitePoolOptions::new()
-                .max_connections(1)
-                .connect(&format!("sqlite://{}", DATABASE_PATH))
-                .await
+    init_db_at(default_db_path()).await
+}
+
+/// Open the database at `path`, creating it if it doesn't exist
+/// The schema is migrated and the bundled items are reseeded if the csv they
+/// came from has changed since the database was last opened
+pub async fn init_db_at<P: AsRef<Path>>(path: P) -> Result<Pool<Sqlite>> {
+    let path = path.as_ref();
+    if path.exists() {
+        tracing::debug!("Database found at {}", path.display());
+    } else {
+        tracing::debug!("Database not found, creating {}", path.display());
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
         }
     }
-    .context("Failed to connect to database object")?;
 
-    ensure_tables(&pool).await?;
-    if do_populate {
-        let res = populate_tables(&pool).await;
-        if res.is_err() {
-            std::fs::remove_file(DATABASE_PATH).unwrap();
-            tracing::error!("Error while populating database: {res:?}");
-            std::process::exit(1);
-        }
+    let options = SqliteConnectOptions::new()
+        .filename(path)
+        .create_if_missing(true);
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect_with(options)
+        .await
+        .context("Failed to connect to database object")?;
+
+    migrate(&pool).await?;
+    if seeded_checksum(&pool).await?.as_deref() != Some(&dataset_checksum()) {
+        tracing::debug!("Bundled csv has changed, reseeding");
+        reseed(&pool)
+            .await
+            .context("Error while populating database")?;
     }
 
     Ok(pool)
 }
 
-pub async fn ensure_tables(pool: &Pool<Sqlite>) -> Result<()> {
-    sqlx::query(
-        "
-        PRAGMA foreign_keys = ON;
-
-        CREATE TABLE IF NOT EXISTS equipment(
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            name TEXT,
-            pfs TEXT,
-            source TEXT,
-            rarity TEXT,
-            trait TEXT,
-            item_category TEXT,
-            item_subcategory TEXT,
-            level INTEGER,
-            price TEXT,
-            bulk TEXT,
-            usage TEXT,
-            spoilers TEXT
-        );
-        ",
+/// Bring the schema up to date
+pub async fn migrate(pool: &Pool<Sqlite>) -> Result<()> {
+    upgrade_legacy(pool).await?;
+    sqlx::migrate!()
+        .run(pool)
+        .await
+        .context("Failed to migrate database")?;
+
+    Ok(())
+}
+
+/// Databases made before migrations were introduced have an equipment table
+/// but no record of which migrations ran
+/// Bring their table in line with the first migration so it can be skipped
+async fn upgrade_legacy(pool: &Pool<Sqlite>) -> Result<()> {
+    let (has_equipment, has_migrations): (bool, bool) = sqlx::query_as(
+        "SELECT
+            EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'equipment'),
+            EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = '_sqlx_migrations');",
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to inspect database")?;
+    if !has_equipment || has_migrations {
+        return Ok(());
+    }
+
+    let (has_origin,): (bool,) = sqlx::query_as(
+        "SELECT COUNT(*) > 0 FROM pragma_table_info('equipment') WHERE name = 'origin';",
     )
-    .execute(pool)
+    .fetch_one(pool)
     .await
-    .context("Failed to ensure tables")?;
+    .context("Failed to inspect equipment table")?;
+    if !has_origin {
+        tracing::debug!("Upgrading database made before migrations");
+        sqlx::query("ALTER TABLE equipment ADD COLUMN origin TEXT NOT NULL DEFAULT 'aon';")
+            .execute(pool)
+            .await
+            .context("Failed to add origin column")?;
+    }
 
     Ok(())
 }
 
-pub async fn populate_tables(pool: &Pool<Sqlite>) -> Result<()> {
-    let csv_path = Path::new(env!("CARGO_MANIFEST_DIR")).join(CSV_PATH);
-    let mut rdr = csv::Reader::from_path(csv_path)?;
+/// Identifies the bundled csv and price overrides, so databases seeded from
+/// an older copy can be detected
+/// FNV-1a, which unlike std's hasher is stable between Rust versions
+pub fn dataset_checksum() -> String {
+    let hash = EQUIPMENT_CSV
+        .bytes()
+        .chain(PRICE_OVERRIDES.bytes())
+        .fold(0xcbf29ce484222325_u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+    format!("{:016x}", hash)
+}
+
+/// The checksum of the csv the bundled items were seeded from, if they have
+/// been
+pub async fn seeded_checksum(pool: &Pool<Sqlite>) -> Result<Option<String>> {
+    let checksum = sqlx::query_scalar("SELECT checksum FROM dataset WHERE id = 1;")
+        .fetch_optional(pool)
+        .await
+        .context("Failed to read dataset checksum")?;
+
+    Ok(checksum)
+}
+
+/// Replace every bundled item with the contents of the bundled csv
+///
+/// Imported items are kept. Where an import replaced a bundled item, the
+/// import wins and the bundled copy isn't added back. The bundled price
+/// overrides are reloaded the same way, so imported prices win. Returns how
+/// many items were loaded.
+pub async fn reseed(pool: &Pool<Sqlite>) -> Result<usize> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM equipment WHERE origin = $1;")
+        .bind(BUNDLED_ORIGIN)
+        .execute(&mut *tx)
+        .await
+        .context("Failed to clear bundled items")?;
+
+    let imported: HashSet<(String, String)> = sqlx::query_as("SELECT name, source FROM equipment;")
+        .fetch_all(&mut *tx)
+        .await
+        .context("Failed to read imported items")?
+        .into_iter()
+        .collect();
+
+    let mut count = 0;
+    let mut trait_ids = TraitIds::new();
+    let mut rdr = csv::Reader::from_reader(EQUIPMENT_CSV.as_bytes());
     for row in rdr.deserialize() {
         let row: DbItem = row?;
+        if imported.contains(&(row.name.clone(), row.source.clone())) {
+            continue;
+        }
+        insert_row(&mut tx, &mut trait_ids, &row, BUNDLED_ORIGIN).await?;
+        count += 1;
+    }
 
-        sqlx::query("INSERT INTO equipment (name, pfs, source, rarity, trait, item_category, item_subcategory, level, price, bulk, usage, spoilers)
-        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12);")
-            .bind(row.name)
-            .bind(row.pfs)
-            .bind(row.source)
-            .bind(row.rarity)
-            .bind(row.r#trait)
-            .bind(row.item_category)
-            .bind(row.item_subcategory)
-            .bind(row.level)
-            .bind(row.price)
-            .bind(row.bulk)
-            .bind(row.usage)
-            .bind(row.spoilers)
-            .execute(pool)
+    sqlx::query("DELETE FROM price_overrides WHERE origin = $1;")
+        .bind(BUNDLED_ORIGIN)
+        .execute(&mut *tx)
+        .await
+        .context("Failed to clear bundled prices")?;
+    for (name, price) in parse_price_overrides(PRICE_OVERRIDES)? {
+        sqlx::query(
+            "INSERT INTO price_overrides (name, price, origin) VALUES ($1, $2, $3)
+            ON CONFLICT(name) DO NOTHING;",
+        )
+        .bind(name)
+        .bind(price)
+        .bind(BUNDLED_ORIGIN)
+        .execute(&mut *tx)
+        .await
+        .context("Failed to insert bundled prices")?;
+    }
+
+    sqlx::query(
+        "INSERT INTO dataset (id, checksum, seeded_at) VALUES (1, $1, $2)
+        ON CONFLICT(id) DO UPDATE SET checksum = excluded.checksum, seeded_at = excluded.seeded_at;",
+    )
+    .bind(dataset_checksum())
+    .bind(chrono::offset::Local::now().to_rfc3339())
+    .execute(&mut *tx)
+    .await
+    .context("Failed to record dataset checksum")?;
+    tx.commit().await?;
+
+    Ok(count)
+}
+
+/// Read a toml table of item names to prices, e.g. `Club = "1 cp"`
+/// Every price must parse
+fn parse_price_overrides(toml: &str) -> Result<Vec<(String, String)>> {
+    let table: std::collections::BTreeMap<String, String> =
+        toml::from_str(toml).context("Failed to parse price overrides")?;
+    for (name, price) in table.iter() {
+        if Price::parse(price)?.is_none() {
+            bail!("Price for {} is empty: {:?}", name, price);
+        }
+    }
+
+    Ok(table.into_iter().collect())
+}
+
+/// Import price overrides from a toml file of item names to prices, e.g.
+/// `Club = "1 cp"`
+///
+/// Imported prices replace any existing override for the same item, including
+/// the bundled ones, and are kept when the database is rebuilt. Returns how
+/// many prices were imported.
+pub async fn import_prices<P: AsRef<Path>>(pool: &Pool<Sqlite>, path: P) -> Result<usize> {
+    let path = path.as_ref();
+    let toml = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    import_price_table(pool, &toml)
+        .await
+        .with_context(|| format!("Failed to import {}", path.display()))
+}
+
+/// Import price overrides from toml text, see `import_prices`
+/// Every price is checked before anything is written
+pub async fn import_price_table(pool: &Pool<Sqlite>, toml: &str) -> Result<usize> {
+    let prices = parse_price_overrides(toml)?;
+
+    let mut tx = pool.begin().await?;
+    for (name, price) in prices.iter() {
+        let known: bool =
+            sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM equipment WHERE name = $1);")
+                .bind(name)
+                .fetch_one(&mut *tx)
+                .await?;
+        if !known {
+            tracing::warn!("Price given for unknown item: {}", name);
+        }
+        sqlx::query(
+            "INSERT INTO price_overrides (name, price, origin) VALUES ($1, $2, $3)
+            ON CONFLICT(name) DO UPDATE SET price = excluded.price, origin = excluded.origin;",
+        )
+        .bind(name)
+        .bind(price)
+        .bind(USER_ORIGIN)
+        .execute(&mut *tx)
+        .await
+        .context("Failed to insert price")?;
+    }
+    tx.commit().await?;
+
+    Ok(prices.len())
+}
+
+/// Every item without a price in coins once overrides are applied, e.g. items
+/// priced "(Varies)", which generation never stocks
+pub async fn get_unpriced(pool: &Pool<Sqlite>) -> Result<Vec<Item>> {
+    let results = sqlx::query_as::<_, DbItem>("SELECT * FROM priced_equipment ORDER BY id;")
+        .fetch_all(pool)
+        .await
+        .context("Failed to retrieve items from db")?;
+
+    let mut items = Vec::new();
+    for row in results {
+        let item = Item::try_from(row)?;
+        if item.price.is_none() {
+            items.push(item);
+        }
+    }
+    Ok(items)
+}
+
+/// How many rows an import added and replaced
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub struct ImportSummary {
+    pub inserted: usize,
+    pub updated: usize,
+}
+
+/// Import items from a csv file with the same columns as the bundled one
+/// See `import_reader`
+pub async fn import_csv<P: AsRef<Path>>(
+    pool: &Pool<Sqlite>,
+    path: P,
+    origin: &str,
+) -> Result<ImportSummary> {
+    let path = path.as_ref();
+    let file =
+        std::fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    import_reader(pool, file, origin)
+        .await
+        .with_context(|| format!("Failed to import {}", path.display()))
+}
+
+/// Import items from csv data with the same columns as the bundled csv
+///
+/// Every row is checked before anything is written, so a bad file changes
+/// nothing. Rows replace existing items with the same name and source, and
+/// are tagged with `origin` so they can be told apart from AoN's items.
+pub async fn import_reader<R: std::io::Read>(
+    pool: &Pool<Sqlite>,
+    reader: R,
+    origin: &str,
+) -> Result<ImportSummary> {
+    if origin.trim().is_empty() {
+        bail!("Source tag must not be empty");
+    }
+
+    let mut rows = vec![];
+    let mut errors = vec![];
+    let mut rdr = csv::Reader::from_reader(reader);
+    for (i, row) in rdr.deserialize::<DbItem>().enumerate() {
+        // the header is line 1
+        let line = i + 2;
+        match row.map_err(anyhow::Error::from).and_then(validate_row) {
+            Ok(row) => rows.push(row),
+            Err(e) => errors.push(format!("line {}: {:#}", line, e)),
+        }
+    }
+    if !errors.is_empty() {
+        bail!("Invalid rows:\n{}", errors.join("\n"));
+    }
+
+    let mut summary = ImportSummary::default();
+    let mut trait_ids = TraitIds::new();
+    let mut tx = pool.begin().await?;
+    for row in rows.iter() {
+        let updated = sqlx::query(
+            "UPDATE equipment
+            SET pfs = $1, rarity = $2, trait = $3, item_category = $4, item_subcategory = $5,
+                level = $6, price = $7, bulk = $8, usage = $9, spoilers = $10, origin = $11
+            WHERE name = $12 AND source = $13;",
+        )
+        .bind(row.pfs)
+        .bind(row.rarity)
+        .bind(&row.r#trait)
+        .bind(row.item_category)
+        .bind(&row.item_subcategory)
+        .bind(row.level)
+        .bind(&row.price)
+        .bind(&row.bulk)
+        .bind(&row.usage)
+        .bind(&row.spoilers)
+        .bind(origin)
+        .bind(&row.name)
+        .bind(&row.source)
+        .execute(&mut *tx)
+        .await
+        .context("Failed to update row")?
+        .rows_affected();
+
+        if updated > 0 {
+            let ids: Vec<i64> =
+                sqlx::query_scalar("SELECT id FROM equipment WHERE name = $1 AND source = $2;")
+                    .bind(&row.name)
+                    .bind(&row.source)
+                    .fetch_all(&mut *tx)
+                    .await
+                    .context("Failed to find updated rows")?;
+            for id in ids {
+                link_traits(&mut tx, &mut trait_ids, id, &row.r#trait).await?;
+            }
+            summary.updated += 1;
+        } else {
+            insert_row(&mut tx, &mut trait_ids, row, origin).await?;
+            summary.inserted += 1;
+        }
+    }
+    tx.commit().await?;
+
+    Ok(summary)
+}
+
+/// Check a row can be used to generate merchants
+/// Rarity and category are already checked when the row is read
+fn validate_row(row: DbItem) -> Result<DbItem> {
+    if row.name.trim().is_empty() {
+        bail!("Item has no name");
+    }
+    Item::try_from(row.clone())?;
+    Ok(row)
+}
+
+async fn insert_row(
+    conn: &mut SqliteConnection,
+    trait_ids: &mut TraitIds,
+    row: &DbItem,
+    origin: &str,
+) -> Result<()> {
+    let id = sqlx::query("INSERT INTO equipment (name, pfs, source, rarity, trait, item_category, item_subcategory, level, price, bulk, usage, spoilers, origin)
+    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13);")
+        .bind(&row.name)
+        .bind(row.pfs)
+        .bind(&row.source)
+        .bind(row.rarity)
+        .bind(&row.r#trait)
+        .bind(row.item_category)
+        .bind(&row.item_subcategory)
+        .bind(row.level)
+        .bind(&row.price)
+        .bind(&row.bulk)
+        .bind(&row.usage)
+        .bind(&row.spoilers)
+        .bind(origin)
+        .execute(&mut *conn)
+        .await
+        .context("Failed to insert row into table")?
+        .last_insert_rowid();
+    link_traits(conn, trait_ids, id, &row.r#trait).await?;
+
+    Ok(())
+}
+
+/// Trait ids by lowercase name, so loading many items doesn't look every
+/// trait up again
+type TraitIds = HashMap<String, i64>;
+
+/// Replace the item's links in the trait table with the traits in `column`
+async fn link_traits(
+    conn: &mut SqliteConnection,
+    trait_ids: &mut TraitIds,
+    item_id: i64,
+    column: &str,
+) -> Result<()> {
+    sqlx::query("DELETE FROM item_traits WHERE item_id = $1;")
+        .bind(item_id)
+        .execute(&mut *conn)
+        .await
+        .context("Failed to unlink traits")?;
+    for t in parse_traits(column) {
+        let key = t.name().to_lowercase();
+        let trait_id = match trait_ids.get(&key) {
+            Some(id) => *id,
+            None => {
+                sqlx::query("INSERT OR IGNORE INTO traits (name) VALUES ($1);")
+                    .bind(t.name())
+                    .execute(&mut *conn)
+                    .await
+                    .context("Failed to insert trait")?;
+                let id: i64 = sqlx::query_scalar("SELECT id FROM traits WHERE name = $1;")
+                    .bind(t.name())
+                    .fetch_one(&mut *conn)
+                    .await
+                    .context("Failed to find trait")?;
+                trait_ids.insert(key, id);
+                id
+            }
+        };
+        sqlx::query("INSERT OR IGNORE INTO item_traits (item_id, trait_id) VALUES ($1, $2);")
+            .bind(item_id)
+            .bind(trait_id)
+            .execute(&mut *conn)
             .await
-            .context("Failed to insert row into table")?;
+            .context("Failed to link trait")?;
     }
 
     Ok(())
@@ -133,28 +580,41 @@ pub async fn get_all(
 
     let q = format!(
         "
-    SELECT * FROM equipment
+    SELECT * FROM priced_equipment
     WHERE rarity = $1
     {}
+    ORDER BY id
     ;",
         priceless_filter
     );
     let results = sqlx::query_as::<_, DbItem>(&q)
-        .bind(rarity.as_ref())
+        .bind(rarity)
         .fetch_all(pool)
         .await
         .context("Failed to retrieve category from db")?;
 
-    Ok(results.into_iter().map(|c| c.into()).collect())
+    priced_items(results, ignore_priceless)
 }
 
-/// Get all items for a given category
-/// Category must match string exactly as it appears on AoN
+/// Convert rows to items, dropping any without a price in coins, like
+/// "(Varies)", if `ignore_priceless` is set
+fn priced_items(rows: Vec<DbItem>, ignore_priceless: bool) -> Result<Vec<Item>> {
+    let mut items = Vec::with_capacity(rows.len());
+    for row in rows {
+        let item = Item::try_from(row)?;
+        if !ignore_priceless || item.price.is_some() {
+            items.push(item);
+        }
+    }
+    Ok(items)
+}
+
+/// Get all items for a given category with a level in `levels`
 pub async fn get_category(
     pool: &Pool<Sqlite>,
     category: ItemCategory,
     rarity: Rarity,
-    level: i32,
+    levels: RangeInclusive<i32>,
     ignore_priceless: bool,
 ) -> Result<Vec<Item>> {
     let priceless_filter = if ignore_priceless {
@@ -165,23 +625,134 @@ pub async fn get_category(
 
     let q = format!(
         "
-    SELECT * FROM equipment
+    SELECT * FROM priced_equipment
     WHERE item_category = $1 
     AND rarity = $2
-    AND level < $3
+    AND level BETWEEN $3 AND $4
     {}
+    ORDER BY id
     ;",
         priceless_filter
     );
     let results = sqlx::query_as::<_, DbItem>(&q)
-        .bind(category.as_ref())
-        .bind(rarity.as_ref())
-        .bind(level)
+        .bind(category)
+        .bind(rarity)
+        .bind(levels.start())
+        .bind(levels.end())
         .fetch_all(pool)
         .await
         .context("Failed to retrieve category from db")?;
 
-    Ok(results.into_iter().map(|c| c.into()).collect())
+    priced_items(results, ignore_priceless)
+}
+
+/// Search the equipment table
+pub async fn search(pool: &Pool<Sqlite>, query: &ItemQuery) -> Result<SearchResults> {
+    let mut q = QueryBuilder::<Sqlite>::new("SELECT * FROM priced_equipment WHERE 1 = 1");
+    if let Some(name) = &query.name {
+        q.push(" AND name LIKE ").push_bind(format!("%{}%", name));
+    }
+    if let Some(min_level) = query.min_level {
+        q.push(" AND level >= ").push_bind(min_level);
+    }
+    if let Some(max_level) = query.max_level {
+        q.push(" AND level <= ").push_bind(max_level);
+    }
+    if let Some(rarity) = query.rarity {
+        q.push(" AND rarity = ").push_bind(rarity);
+    }
+    if let Some(category) = query.category {
+        q.push(" AND item_category = ").push_bind(category);
+    }
+    if let Some(t) = &query.has_trait {
+        q.push(
+            " AND EXISTS (SELECT 1 FROM item_traits JOIN traits ON traits.id = item_traits.trait_id
+            WHERE item_traits.item_id = priced_equipment.id AND traits.name = ",
+        )
+        .push_bind(t.trim().to_string())
+        .push(")");
+    }
+    for (traits, exists) in [
+        (&query.any_traits, "EXISTS"),
+        (&query.excluded_traits, "NOT EXISTS"),
+    ] {
+        if traits.is_empty() {
+            continue;
+        }
+        q.push(format!(
+            " AND {} (SELECT 1 FROM item_traits JOIN traits ON traits.id = item_traits.trait_id
+            WHERE item_traits.item_id = priced_equipment.id AND traits.name IN (",
+            exists
+        ));
+        let mut names = q.separated(", ");
+        for t in traits.iter() {
+            names.push_bind(t.trim().to_string());
+        }
+        q.push("))");
+    }
+    if let Some(subcategory) = &query.subcategory {
+        q.push(" AND item_subcategory = ")
+            .push_bind(subcategory.clone())
+            .push(" COLLATE NOCASE");
+    }
+    if let Some(source) = &query.source {
+        q.push(" AND source LIKE ")
+            .push_bind(format!("%{}%", source));
+    }
+    q.push(" ORDER BY id;");
+
+    let results = q
+        .build_query_as::<DbItem>()
+        .fetch_all(pool)
+        .await
+        .context("Failed to search db")?;
+
+    let price_of = |i: &Item| i.price.as_ref().map(|p| p.as_cp());
+    let mut items = results
+        .into_iter()
+        .map(Item::try_from)
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .filter(|i| match &query.bulk {
+            Some(bulk) => i.bulk == *bulk,
+            None => true,
+        })
+        .filter(|i| match query.min_price {
+            Some(min) => price_of(i).is_some_and(|p| p >= min),
+            None => true,
+        })
+        .filter(|i| match query.max_price {
+            Some(max) => price_of(i).is_some_and(|p| p <= max),
+            None => true,
+        })
+        .collect::<Vec<_>>();
+
+    items.sort_by(|a, b| {
+        let ord = match query.sort_by {
+            SortBy::Name => std::cmp::Ordering::Equal,
+            SortBy::Level => a.level.cmp(&b.level),
+            // unpriced items sort after everything else
+            SortBy::Price => match (price_of(a), price_of(b)) {
+                (Some(a), Some(b)) => a.cmp(&b),
+                (Some(_), None) => std::cmp::Ordering::Less,
+                (None, Some(_)) => std::cmp::Ordering::Greater,
+                (None, None) => std::cmp::Ordering::Equal,
+            },
+        };
+        ord.then_with(|| a.name.cmp(&b.name))
+    });
+    if query.descending {
+        items.reverse();
+    }
+
+    let total = items.len();
+    let items = items
+        .into_iter()
+        .skip(query.offset)
+        .take(query.limit.unwrap_or(usize::MAX))
+        .collect();
+
+    Ok(SearchResults { items, total })
 }
 
 /// Lists the available options in a column
@@ -202,44 +773,261 @@ pub async fn get_distinct<S: AsRef<str>>(
     Ok(results)
 }
 
-pub async fn get_rations(pool: &Pool<Sqlite>) -> Item {
+/// Get the first item with the given name
+/// Name must match string exactly as it appears on AoN
+pub async fn get_by_name<S: AsRef<str>>(pool: &Pool<Sqlite>, name: S) -> Result<Option<Item>> {
     let result = sqlx::query_as::<_, DbItem>(
         "
-        SELECT * FROM equipment 
-        WHERE name = $1;
+        SELECT * FROM priced_equipment
+        WHERE name = $1
+        ORDER BY id;
         ",
     )
-    .bind("Rations")
-    .fetch_one(pool)
+    .bind(name.as_ref())
+    .fetch_optional(pool)
     .await
-    .context("Rations should exist.")
-    .unwrap();
+    .context("Failed to retrieve item from db")?;
 
-    result.into()
+    result.map(Item::try_from).transpose()
 }
 
+/// The price of the cheapest common item in each category with a level in
+/// `levels`
+/// Load an `ItemCatalog` instead when looking up more than this
 pub async fn get_min_for_each_category(
     pool: &Pool<Sqlite>,
-    level: i32,
+    levels: RangeInclusive<i32>,
 ) -> Result<HashMap<ItemCategory, i32>> {
-    let mut out = HashMap::new();
-    for category in enum_iterator::all::<ItemCategory>() {
-        let items = get_category(pool, category, Rarity::Common, level, true).await?;
-        let min = items
-            .into_iter()
-            .reduce(|a, b| {
-                let lhs_price = a.price.as_ref().unwrap().as_cp();
-                let rhs_price = b.price.as_ref().unwrap().as_cp();
-                if lhs_price < rhs_price {
-                    a
-                } else {
-                    b
-                }
-            })
-            .map(|i| i.price.as_ref().unwrap().as_cp());
-        if let Some(min) = min {
-            out.insert(category, min);
+    Ok(ItemCatalog::load(pool).await?.minimums(levels))
+}
+
+/// An in-memory database populated from the bundled csv
+#[cfg(test)]
+pub(crate) async fn test_pool() -> Pool<Sqlite> {
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .idle_timeout(None)
+        .max_lifetime(None)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+    migrate(&pool).await.unwrap();
+    reseed(&pool).await.unwrap();
+    pool
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        database::{
+            dataset_checksum, get_by_name, get_unpriced, import_price_table, import_reader,
+            migrate, reseed, search, seeded_checksum, test_pool, ItemQuery, SortBy,
+        },
+        item::{ItemCategory, Rarity},
+    };
+
+    const HEADER: &str = "name,pfs,source,rarity,trait,item_category,item_subcategory,level,price,bulk,usage,spoilers";
+
+    #[tokio::test]
+    async fn search_filters_and_pages() {
+        let pool = test_pool().await;
+        let query = ItemQuery {
+            max_level: Some(5),
+            rarity: Some(Rarity::Uncommon),
+            category: Some(ItemCategory::HeldItems),
+            max_price: Some(200 * 100),
+            sort_by: SortBy::Price,
+            ..Default::default()
+        };
+        let all = search(&pool, &query).await.unwrap();
+        assert!(all.total > 0);
+        assert_eq!(all.items.len(), all.total);
+        for pair in all.items.windows(2) {
+            assert!(
+                pair[0].price.as_ref().unwrap().as_cp() <= pair[1].price.as_ref().unwrap().as_cp()
+            );
+        }
+        for item in all.items.iter() {
+            assert!(item.level <= 5);
+            assert_eq!(item.rarity, Rarity::Uncommon);
+            assert_eq!(item.item_category, ItemCategory::HeldItems);
+            assert!(item.price.as_ref().unwrap().as_cp() <= 200 * 100);
         }
+
+        let page = search(
+            &pool,
+            &ItemQuery {
+                limit: Some(2),
+                offset: 1,
+                ..query
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(page.total, all.total);
+        assert_eq!(page.items, all.items[1..3]);
+
+        let high_tech = search(
+            &pool,
+            &ItemQuery {
+                category: Some(ItemCategory::HighTech),
+                has_trait: Some("tech".to_string()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(high_tech.total, 2);
+
+        let no_tech = search(
+            &pool,
+            &ItemQuery {
+                category: Some(ItemCategory::HighTech),
+                excluded_traits: vec!["Tech".to_string(), "Cursed".to_string()],
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        let any_high_tech = search(
+            &pool,
+            &ItemQuery {
+                category: Some(ItemCategory::HighTech),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(no_tech.total, any_high_tech.total - 2);
+        assert!(no_tech.items.iter().all(|i| !i.has_trait("tech")));
+
+        let elixirs_or_bombs = search(
+            &pool,
+            &ItemQuery {
+                any_traits: vec!["elixir".to_string(), "Bomb".to_string()],
+                max_level: Some(1),
+                limit: Some(1000),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert!(elixirs_or_bombs
+            .items
+            .iter()
+            .all(|i| i.has_trait("Elixir") || i.has_trait("Bomb")));
+        assert!(elixirs_or_bombs.items.iter().any(|i| i.has_trait("Elixir")));
+        assert!(elixirs_or_bombs.items.iter().any(|i| i.has_trait("Bomb")));
+    }
+
+    #[tokio::test]
+    async fn import_upserts_by_name_and_source() {
+        let pool = test_pool().await;
+        let rations = get_by_name(&pool, "Rations").await.unwrap().unwrap();
+
+        let bad = format!(
+            "{HEADER}\nGood Item,,Homebrew,common,,Held Items,,1,5 gp,L,,\nBad Item,,Homebrew,legendary,,Held Items,,1,5 gp,L,,\n"
+        );
+        let err = import_reader(&pool, bad.as_bytes(), "homebrew")
+            .await
+            .unwrap_err();
+        assert!(format!("{:#}", err).contains("line 3"));
+        assert!(get_by_name(&pool, "Good Item").await.unwrap().is_none());
+
+        let good = format!(
+            "{HEADER}\nGood Item,,Homebrew,common,,held items,,1,5 gp,L,,\nRations,,\"{}\",Common,,Adventuring Gear,,0,5 sp,L,,\n",
+            rations.source
+        );
+        let summary = import_reader(&pool, good.as_bytes(), "homebrew")
+            .await
+            .unwrap();
+        assert_eq!(summary.inserted, 1);
+        assert_eq!(summary.updated, 1);
+
+        let item = get_by_name(&pool, "Good Item").await.unwrap().unwrap();
+        assert_eq!(item.rarity, Rarity::Common);
+        assert_eq!(item.item_category, ItemCategory::HeldItems);
+        let rations = get_by_name(&pool, "Rations").await.unwrap().unwrap();
+        assert_eq!(rations.price.unwrap().as_cp(), 50);
+    }
+
+    #[tokio::test]
+    async fn reseed_keeps_imports() {
+        let pool = test_pool().await;
+        assert_eq!(
+            seeded_checksum(&pool).await.unwrap(),
+            Some(dataset_checksum())
+        );
+        let rations = get_by_name(&pool, "Rations").await.unwrap().unwrap();
+        let csv = format!(
+            "{HEADER}\nGood Item,,Homebrew,Common,,Held Items,,1,5 gp,L,,\nRations,,\"{}\",Common,,Adventuring Gear,,0,5 sp,L,,\n",
+            rations.source
+        );
+        import_reader(&pool, csv.as_bytes(), "homebrew")
+            .await
+            .unwrap();
+
+        let count = reseed(&pool).await.unwrap();
+        let (total,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM equipment;")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(total as usize, count + 2);
+        let (rations,): (i64,) =
+            sqlx::query_as("SELECT COUNT(*) FROM equipment WHERE name = 'Rations';")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(rations, 1);
+        assert!(get_by_name(&pool, "Good Item").await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn price_overrides() {
+        let pool = test_pool().await;
+        let club = get_by_name(&pool, "Club").await.unwrap().unwrap();
+        assert_eq!(club.price.unwrap().as_cp(), 1);
+        let unpriced = get_unpriced(&pool).await.unwrap();
+        assert!(!unpriced.is_empty());
+        assert!(unpriced.iter().all(|i| i.name != "Club"));
+        assert!(unpriced.iter().any(|i| i.name == "Alchemical Bomb"));
+
+        assert!(import_price_table(&pool, "Club = \"1 ep\"").await.is_err());
+        let count = import_price_table(&pool, "club = \"2 cp\"\nRations = \"1 sp\"")
+            .await
+            .unwrap();
+        assert_eq!(count, 2);
+        reseed(&pool).await.unwrap();
+        let club = get_by_name(&pool, "Club").await.unwrap().unwrap();
+        assert_eq!(club.price.unwrap().as_cp(), 2);
+        let rations = get_by_name(&pool, "Rations").await.unwrap().unwrap();
+        assert_eq!(rations.price.unwrap().as_cp(), 10);
+    }
+
+    #[tokio::test]
+    async fn upgrades_legacy_database() {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::query(
+            "CREATE TABLE equipment(
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT, pfs TEXT, source TEXT, rarity TEXT, trait TEXT, item_category TEXT,
+                item_subcategory TEXT, level INTEGER, price TEXT, bulk TEXT, usage TEXT, spoilers TEXT
+            );
+            INSERT INTO equipment (name, source, rarity, item_category, level, price)
+            VALUES ('Stale Item', 'Old Book', 'Common', 'Held Items', 1, '1 gp');",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        migrate(&pool).await.unwrap();
+        assert_eq!(seeded_checksum(&pool).await.unwrap(), None);
+        reseed(&pool).await.unwrap();
+        assert!(get_by_name(&pool, "Stale Item").await.unwrap().is_none());
+        assert!(get_by_name(&pool, "Rations").await.unwrap().is_some());
     }
-    Ok(out)
 }
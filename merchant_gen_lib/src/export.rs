@@ -0,0 +1,4 @@
+//! Exporters that turn a `Merchant` into formats other programs can consume
+
+pub mod foundry;
+pub mod json;
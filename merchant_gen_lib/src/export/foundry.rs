@@ -0,0 +1,162 @@
+//! Export a merchant as a Foundry VTT (pf2e system) loot actor
+//!
+//! The document can be imported into Foundry through "Import Data" on a loot
+//! actor. The actor uses the merchant sheet and every stack becomes an owned item.
+
+use anyhow::Result;
+use serde_json::{json, Value};
+
+use crate::{
+    item::{Item, ItemCategory},
+    merchant::Merchant,
+    settlement::Settlement,
+};
+
+const ACTOR_IMG: &str = "systems/pf2e/icons/default-icons/loot.svg";
+
+/// The merchant as a Foundry loot actor named `name`
+pub fn to_value<S: AsRef<str>>(merchant: &Merchant, name: S) -> Value {
+    let items = merchant
+        .inventory()
+        .sorted()
+        .into_iter()
+        .map(|stack| item_to_value(&stack.item, stack.quantity))
+        .collect::<Vec<_>>();
+
+    json!({
+        "name": name.as_ref(),
+        "type": "loot",
+        "img": ACTOR_IMG,
+        "system": {
+            "lootSheetType": "Merchant",
+            "hiddenWhenEmpty": false,
+            "details": {
+                "description": "",
+                "level": { "value": merchant.level() },
+            },
+        },
+        "items": items,
+    })
+}
+
+/// Serialize the merchant as a pretty printed Foundry loot actor
+pub fn to_string<S: AsRef<str>>(merchant: &Merchant, name: S) -> Result<String> {
+    Ok(serde_json::to_string_pretty(&to_value(merchant, name))?)
+}
+
+/// Every merchant in the settlement as an array of Foundry loot actors
+/// Each element can be imported on its own
+pub fn settlement_to_string(settlement: &Settlement) -> Result<String> {
+    let actors = settlement
+        .merchants()
+        .iter()
+        .map(|merchant| to_value(merchant, merchant.name()))
+        .collect::<Vec<_>>();
+    Ok(serde_json::to_string_pretty(&actors)?)
+}
+
+fn item_to_value(item: &Item, quantity: u32) -> Value {
+    let [pp, gp, sp, cp] = item.price.as_ref().map(|p| p.coins()).unwrap_or_default();
+    let per = item.price.as_ref().map_or(1, |p| p.batch());
+    let traits = item
+        .traits
+        .iter()
+        .map(|t| slug(t.name()))
+        .collect::<Vec<_>>();
+
+    json!({
+        "name": item.name,
+        "type": item_type(item),
+        "system": {
+            "level": { "value": item.level },
+            "quantity": quantity,
+            "price": {
+                "value": { "pp": pp, "gp": gp, "sp": sp, "cp": cp },
+                "per": per,
+            },
+            // Foundry stores bulk as a number, where light bulk is 0.1
+            "bulk": { "value": item.bulk.value().unwrap_or(0.0) },
+            "traits": {
+                "value": traits,
+                "rarity": slug(item.rarity.as_ref()),
+            },
+            "publication": { "title": item.source },
+        },
+    })
+}
+
+/// The pf2e item type the item should be imported as
+fn item_type(item: &Item) -> &'static str {
+    match item.item_category {
+        // ammunition like arrows is sold in batches, and imports as a consumable
+        ItemCategory::Weapons if item.price.as_ref().is_some_and(|p| p.batch() > 1) => "consumable",
+        ItemCategory::Weapons => "weapon",
+        ItemCategory::Armor => "armor",
+        ItemCategory::Shields => "shield",
+        ItemCategory::TradeGoods => "treasure",
+        _ if item.has_trait("Consumable") => "consumable",
+        _ => "equipment",
+    }
+}
+
+/// Foundry identifies traits and rarities by lowercase, hyphenated slugs
+fn slug(s: &str) -> String {
+    s.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        database::{self, test_pool},
+        export::foundry::{slug, to_value},
+        item::Bulk,
+        merchant::Merchant,
+    };
+
+    #[test]
+    fn bulk_and_slugs() {
+        let input = [
+            ("L", 0.1),
+            ("1", 1.0),
+            ("", 0.0),
+            ("L when not activated", 0.1),
+        ];
+        for (input, expected) in input {
+            assert_eq!(Bulk::from(input).value(), Some(expected));
+        }
+
+        assert_eq!(slug("Uncommon"), "uncommon");
+        assert_eq!(slug("Thrown 10 ft."), "thrown-10-ft");
+        assert_eq!(slug("Twin (Sword)"), "twin-sword");
+    }
+
+    #[tokio::test]
+    async fn stacks_become_items() {
+        let pool = test_pool().await;
+        let mut merchant = Merchant::by_level(3);
+        for (name, quantity) in [("Arrows", 20), ("Longsword", 2)] {
+            let item = database::get_by_name(&pool, name).await.unwrap().unwrap();
+            merchant.sell(item, quantity, Some(0)).unwrap();
+        }
+
+        let actor = to_value(&merchant, "Test");
+        let items = actor["items"].as_array().unwrap();
+        let item = |name: &str| items.iter().find(|i| i["name"] == name).unwrap();
+
+        let arrows = item("Arrows");
+        assert_eq!(arrows["type"], "consumable");
+        assert_eq!(arrows["system"]["quantity"], 20);
+        assert_eq!(arrows["system"]["price"]["value"]["sp"], 1);
+        assert_eq!(arrows["system"]["price"]["per"], 10);
+
+        let longsword = item("Longsword");
+        assert_eq!(longsword["type"], "weapon");
+        assert_eq!(longsword["system"]["quantity"], 2);
+        assert_eq!(longsword["system"]["price"]["value"]["gp"], 1);
+        assert_eq!(longsword["system"]["price"]["per"], 1);
+    }
+}
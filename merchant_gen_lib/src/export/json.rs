@@ -0,0 +1,244 @@
+//! A stable JSON representation of a merchant
+//!
+//! The schema is versioned through `schema_version`. Fields are only ever added
+//! within a version; renaming or removing a field bumps the version.
+//!
+//! ```json
+//! {
+//!   "schema_version": 1,
+//!   "merchant": {
+//!     "level": 3,
+//!     "kind": "alchemist",
+//!     "wealth_cp": 16600,
+//!     "stock_value_cp": 16482
+//!   },
+//!   "generation": { "seed": 42, "profile": { ... } },
+//!   "transactions": [
+//!     {
+//!       "kind": "Buy",
+//!       "item": "Rations",
+//!       "quantity": 2,
+//!       "price_cp": 80,
+//!       "timestamp": "2025-01-20T19:03:11.5+01:00"
+//!     }
+//!   ],
+//!   "stock": [
+//!     {
+//!       "name": "Rations",
+//!       "category": "Adventuring Gear",
+//!       "subcategory": "",
+//!       "rarity": "Common",
+//!       "level": 0,
+//!       "traits": [],
+//!       "bulk": "L",
+//!       "source": "Player Core pg. 289 <sup>2.0</sup>",
+//!       "price": "4 sp (1 week)",
+//!       "unit_price_cp": 40,
+//!       "quantity": 10,
+//!       "total_price_cp": 400,
+//!       "batch_size": 1,
+//!       "batch_price_cp": 40
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! A settlement wraps the merchants above under the same schema version:
+//!
+//! ```json
+//! {
+//!   "schema_version": 1,
+//!   "settlement": { "level": 5, "size": "town", "seed": 42 },
+//!   "merchants": [ { "schema_version": 1, "merchant": { ... }, ... } ]
+//! }
+//! ```
+
+use anyhow::Result;
+
+use crate::{
+    item::Item, ledger::Transaction, merchant::Merchant, profile::MerchantProfile,
+    settlement::Settlement,
+};
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct MerchantJson {
+    pub schema_version: u32,
+    pub merchant: MerchantInfo,
+    pub generation: GenerationInfo,
+    /// Every purchase and sale made with the merchant, oldest first
+    pub transactions: Vec<Transaction>,
+    /// Identical items grouped together, sorted by category then name
+    pub stock: Vec<StockEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SettlementJson {
+    pub schema_version: u32,
+    pub settlement: SettlementInfo,
+    pub merchants: Vec<MerchantJson>,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SettlementInfo {
+    pub level: i32,
+    /// One of village, town or city
+    pub size: String,
+    /// `null` if the settlement wasn't generated from a known seed
+    pub seed: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct MerchantInfo {
+    pub level: i32,
+    /// One of generic, blacksmith, alchemist, arcanist or general-store
+    pub kind: String,
+    /// The merchant's wealth in cp
+    pub wealth_cp: i32,
+    /// The total price of everything in stock in cp
+    pub stock_value_cp: i32,
+}
+
+/// Everything needed to regenerate the merchant's stock
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct GenerationInfo {
+    /// `null` if the stock wasn't generated from a known seed
+    pub seed: Option<u64>,
+    pub profile: MerchantProfile,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct StockEntry {
+    pub name: String,
+    pub category: String,
+    pub subcategory: String,
+    pub rarity: String,
+    pub level: i32,
+    pub traits: Vec<String>,
+    pub bulk: String,
+    pub source: String,
+    /// The price as it appears on AoN, `null` for items without a price
+    pub price: Option<String>,
+    /// The price of one item, rounded up to a whole cp for items sold in batches
+    pub unit_price_cp: Option<i32>,
+    pub quantity: u32,
+    pub total_price_cp: Option<i32>,
+    /// How many items the price buys, e.g. 10 for "1 sp (price for 10)"
+    pub batch_size: u32,
+    pub batch_price_cp: Option<i32>,
+}
+
+impl From<&Merchant> for MerchantJson {
+    fn from(merchant: &Merchant) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            merchant: MerchantInfo {
+                level: merchant.level(),
+                kind: merchant.kind().to_string(),
+                wealth_cp: merchant.wealth(),
+                stock_value_cp: merchant.get_wealth_in_inv(),
+            },
+            generation: GenerationInfo {
+                seed: merchant.seed(),
+                profile: merchant.profile(),
+            },
+            transactions: merchant.transactions().to_vec(),
+            stock: merchant
+                .inventory()
+                .sorted()
+                .into_iter()
+                .map(|stack| StockEntry::new(&stack.item, stack.quantity))
+                .collect(),
+        }
+    }
+}
+
+impl From<&Settlement> for SettlementJson {
+    fn from(settlement: &Settlement) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            settlement: SettlementInfo {
+                level: settlement.level(),
+                size: settlement.size().to_string(),
+                seed: settlement.seed(),
+            },
+            merchants: settlement
+                .merchants()
+                .iter()
+                .map(MerchantJson::from)
+                .collect(),
+        }
+    }
+}
+
+impl StockEntry {
+    fn new(item: &Item, quantity: u32) -> Self {
+        let price = item.price.as_ref();
+        Self {
+            name: item.name.clone(),
+            category: item.item_category.to_string(),
+            subcategory: item.item_subcategory.clone(),
+            rarity: item.rarity.to_string(),
+            level: item.level,
+            traits: item.traits.iter().map(|t| t.to_string()).collect(),
+            bulk: item.bulk.to_string(),
+            source: item.source.clone(),
+            price: item.price.as_ref().map(|p| p.to_string()),
+            unit_price_cp: price.map(|p| p.cost_cp(1)),
+            quantity,
+            total_price_cp: price.map(|p| p.cost_cp(quantity)),
+            batch_size: price.map_or(1, |p| p.batch()),
+            batch_price_cp: price.map(|p| p.as_cp()),
+        }
+    }
+}
+
+/// Serialize the merchant as pretty printed JSON
+pub fn to_string(merchant: &Merchant) -> Result<String> {
+    Ok(serde_json::to_string_pretty(&MerchantJson::from(merchant))?)
+}
+
+/// Serialize the settlement as pretty printed JSON
+pub fn settlement_to_string(settlement: &Settlement) -> Result<String> {
+    Ok(serde_json::to_string_pretty(&SettlementJson::from(
+        settlement,
+    ))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        database::test_pool,
+        export::json::{MerchantJson, SCHEMA_VERSION},
+        kind::MerchantKind,
+        merchant::Merchant,
+    };
+
+    #[tokio::test]
+    async fn stacks_and_totals() {
+        let pool = test_pool().await;
+        let mut merchant = Merchant::by_level(4).with_kind(MerchantKind::GeneralStore);
+        merchant
+            .generate_inventory_with_seed(&pool, 7)
+            .await
+            .unwrap();
+
+        let json = merchant.json().unwrap();
+        let parsed: MerchantJson = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.schema_version, SCHEMA_VERSION);
+        assert_eq!(parsed.generation.seed, Some(7));
+        assert_eq!(parsed.merchant.kind, "general-store");
+
+        let quantity: u32 = parsed.stock.iter().map(|e| e.quantity).sum();
+        assert_eq!(quantity as usize, merchant.len());
+        let total: i32 = parsed.stock.iter().filter_map(|e| e.total_price_cp).sum();
+        assert_eq!(total, parsed.merchant.stock_value_cp);
+
+        let rations = parsed.stock.iter().find(|e| e.name == "Rations").unwrap();
+        assert_eq!(
+            rations.total_price_cp,
+            rations.unit_price_cp.map(|p| p * rations.quantity as i32)
+        );
+    }
+}
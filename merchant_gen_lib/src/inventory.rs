@@ -0,0 +1,278 @@
+use anyhow::{bail, Context, Result};
+
+use crate::item::Item;
+
+/// Some number of identical items
+#[derive(Debug, Clone, Eq, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Stack {
+    pub item: Item,
+    pub quantity: u32,
+}
+
+impl Stack {
+    /// The price of the whole stack in cp, if the item has a price
+    pub fn value_cp(&self) -> Option<i32> {
+        self.item.price.as_ref().map(|p| p.cost_cp(self.quantity))
+    }
+
+    /// The quantity, counted in bundles for items sold in batches,
+    /// e.g. "x20 (2 bundles)"
+    pub fn quantity_label(&self) -> String {
+        let batch = self.item.price.as_ref().map_or(1, |p| p.batch());
+        let bundles = self.quantity / batch;
+        let loose = self.quantity % batch;
+        let plural = if bundles == 1 { "bundle" } else { "bundles" };
+        match (batch, bundles, loose) {
+            (1, _, _) | (_, 0, _) => format!("x{}", self.quantity),
+            (_, _, 0) => format!("x{} ({} {})", self.quantity, bundles, plural),
+            _ => format!(
+                "x{} ({} {}, {} loose)",
+                self.quantity, bundles, plural, loose
+            ),
+        }
+    }
+
+    fn display_order(a: &Stack, b: &Stack) -> std::cmp::Ordering {
+        (a.item.item_category.as_ref(), &a.item.name)
+            .cmp(&(b.item.item_category.as_ref(), &b.item.name))
+    }
+}
+
+/// A merchant's stock, with identical items grouped into stacks
+#[derive(Debug, Default, Clone, Eq, PartialEq, serde::Serialize)]
+#[serde(transparent)]
+pub struct Inventory {
+    stacks: Vec<Stack>,
+}
+
+impl Inventory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `quantity` of `item`, stacking it with identical items
+    /// A stack holds at most `u32::MAX` items, see `room_for`
+    pub fn add(&mut self, item: Item, quantity: u32) {
+        if quantity == 0 {
+            return;
+        }
+        match self.stacks.iter_mut().find(|s| s.item == item) {
+            Some(stack) => stack.quantity = stack.quantity.saturating_add(quantity),
+            None => self.stacks.push(Stack { item, quantity }),
+        }
+    }
+
+    /// How many more of `item` fit on its stack
+    pub fn room_for(&self, item: &Item) -> u32 {
+        self.stacks
+            .iter()
+            .find(|s| s.item == *item)
+            .map_or(u32::MAX, |s| u32::MAX - s.quantity)
+    }
+
+    /// Remove `quantity` of the item called `name` (case insensitive)
+    /// Fails without changing anything if there aren't enough in stock
+    pub fn remove(&mut self, name: &str, quantity: u32) -> Result<Item> {
+        let index = self
+            .stacks
+            .iter()
+            .position(|s| s.item.name.eq_ignore_ascii_case(name))
+            .with_context(|| format!("{} is not in stock", name))?;
+        self.remove_at(index, quantity)
+    }
+
+    /// Remove `quantity` of exactly `item`
+    /// Fails without changing anything if there aren't enough in stock
+    pub fn remove_item(&mut self, item: &Item, quantity: u32) -> Result<Item> {
+        let index = self
+            .stacks
+            .iter()
+            .position(|s| s.item == *item)
+            .with_context(|| format!("{} is not in stock", item.name))?;
+        self.remove_at(index, quantity)
+    }
+
+    fn remove_at(&mut self, index: usize, quantity: u32) -> Result<Item> {
+        let stack = &mut self.stacks[index];
+        if stack.quantity < quantity {
+            bail!(
+                "Only {} {} in stock, {} requested",
+                stack.quantity,
+                stack.item.name,
+                quantity
+            );
+        }
+        stack.quantity -= quantity;
+        let item = stack.item.clone();
+        if stack.quantity == 0 {
+            self.stacks.remove(index);
+        }
+
+        Ok(item)
+    }
+
+    /// Move every stack from `other` into this inventory
+    pub fn merge(&mut self, other: Inventory) {
+        for stack in other.stacks {
+            self.add(stack.item, stack.quantity);
+        }
+    }
+
+    /// The stack for the item called `name` (case insensitive)
+    pub fn get(&self, name: &str) -> Option<&Stack> {
+        self.stacks
+            .iter()
+            .find(|s| s.item.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Stack> {
+        self.stacks.iter()
+    }
+
+    /// The total number of items, counting every item in each stack
+    pub fn len(&self) -> usize {
+        self.stacks.iter().map(|s| s.quantity as usize).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stacks.is_empty()
+    }
+
+    /// The price of everything in stock in cp
+    pub fn value_cp(&self) -> i32 {
+        self.stacks
+            .iter()
+            .filter_map(|s| s.value_cp())
+            .fold(0, i32::saturating_add)
+    }
+
+    /// Sort stacks by category then name
+    pub fn sort(&mut self) {
+        self.stacks.sort_by(Stack::display_order);
+    }
+
+    /// The stacks sorted by category then name, without sorting the inventory
+    pub fn sorted(&self) -> Vec<&Stack> {
+        let mut stacks = self.stacks.iter().collect::<Vec<_>>();
+        stacks.sort_by(|a, b| Stack::display_order(a, b));
+        stacks
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Inventory {
+    /// Accepts both stacks and the flat list of items older versions saved
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(serde::Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Stacks(Vec<Stack>),
+            Flat(Vec<Item>),
+        }
+
+        Ok(match Repr::deserialize(deserializer)? {
+            Repr::Stacks(stacks) => Inventory { stacks },
+            Repr::Flat(items) => {
+                let mut inventory = Inventory::new();
+                for item in items {
+                    inventory.add(item, 1);
+                }
+                inventory
+            }
+        })
+    }
+}
+
+impl<'a> IntoIterator for &'a Inventory {
+    type Item = &'a Stack;
+    type IntoIter = std::slice::Iter<'a, Stack>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.stacks.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        database::{self, test_pool},
+        inventory::Inventory,
+        kind::MerchantKind,
+        merchant::Merchant,
+    };
+
+    const LEGACY: &str = r#"(wealth:5833,level:1,inventory:[(name:"Rations",pfs:"Standard",source:"Player Core pg. 290 <sup>2.0</sup>",rarity:"Common",trait:"",item_category:"Adventuring Gear",item_subcategory:"",level:0,price:Some((text:"4 sp (1 week)",pp:0,gp:0,sp:4,cp:0)),bulk:"L",usage:"",spoilers:""),(name:"Rations",pfs:"Standard",source:"Player Core pg. 290 <sup>2.0</sup>",rarity:"Common",trait:"",item_category:"Adventuring Gear",item_subcategory:"",level:0,price:Some((text:"4 sp (1 week)",pp:0,gp:0,sp:4,cp:0)),bulk:"L",usage:"",spoilers:"")])"#;
+
+    #[test]
+    fn loads_flat_inventory() {
+        let merchant: Merchant = ron::from_str(LEGACY).unwrap();
+        assert_eq!(merchant.len(), 2);
+        assert_eq!(merchant.inventory().iter().count(), 1);
+        assert_eq!(merchant.inventory().get("rations").unwrap().quantity, 2);
+
+        let ron = ron::to_string(&merchant).unwrap();
+        let reloaded: Merchant = ron::from_str(&ron).unwrap();
+        assert_eq!(reloaded, merchant);
+    }
+
+    #[test]
+    fn add_remove_merge() {
+        let merchant: Merchant = ron::from_str(LEGACY).unwrap();
+        let rations = merchant.inventory().get("Rations").unwrap().item.clone();
+
+        let mut inventory = Inventory::new();
+        inventory.add(rations.clone(), 3);
+        assert!(inventory.remove("Rations", 4).is_err());
+        assert_eq!(inventory.len(), 3);
+        assert_eq!(inventory.remove("Rations", 3).unwrap(), rations);
+        assert!(inventory.is_empty());
+
+        inventory.add(rations.clone(), 1);
+        inventory.merge(merchant.inventory().clone());
+        assert_eq!(inventory.len(), 3);
+        assert_eq!(inventory.value_cp(), 3 * 40);
+
+        // a full stack stays full rather than wrapping round
+        assert_eq!(inventory.room_for(&rations), u32::MAX - 3);
+        inventory.add(rations.clone(), u32::MAX);
+        assert_eq!(inventory.room_for(&rations), 0);
+        assert_eq!(inventory.get("Rations").unwrap().quantity, u32::MAX);
+    }
+
+    #[tokio::test]
+    async fn batched_stacks() {
+        let pool = test_pool().await;
+        let arrows = database::get_by_name(&pool, "Arrows")
+            .await
+            .unwrap()
+            .unwrap();
+        let price = arrows.price.clone().unwrap();
+        assert_eq!(price.batch(), 10);
+        assert_eq!(price.unit_cp(), 1.0);
+
+        let mut merchant = Merchant::by_level(1);
+        merchant.sell(arrows.clone(), 20, Some(0)).unwrap();
+        assert!(merchant.sell(arrows, u32::MAX, Some(0)).is_err());
+        let stack = merchant.inventory().get("Arrows").unwrap();
+        assert_eq!(stack.value_cp(), Some(20));
+        assert_eq!(stack.quantity_label(), "x20 (2 bundles)");
+        assert!(merchant
+            .to_string()
+            .contains("Arrows x20 (2 bundles) - 1 sp"));
+
+        let transaction = merchant.buy("Arrows", 3).unwrap();
+        assert_eq!(transaction.price_cp, 3);
+        let stack = merchant.inventory().get("Arrows").unwrap();
+        assert_eq!(stack.quantity_label(), "x17 (1 bundle, 7 loose)");
+
+        // fresh stock always comes in whole batches
+        let mut blacksmith = Merchant::by_level(5).with_kind(MerchantKind::Blacksmith);
+        blacksmith
+            .generate_inventory_with_seed(&pool, 7)
+            .await
+            .unwrap();
+        for stack in blacksmith.inventory().iter() {
+            let batch = stack.item.price.as_ref().unwrap().batch();
+            assert_eq!(stack.quantity % batch, 0, "{}", stack.item.name);
+        }
+    }
+}
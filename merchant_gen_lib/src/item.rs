@@ -1,134 +1,468 @@
 use anyhow::{Context, Result};
-use regex::Regex;
 
 use crate::database::DbItem;
 
-// r#""^(?:(\d* \w{2}),? ?)*(.*)?\n"#
-lazy_static::lazy_static! {
-    static ref PRICE_RE: Regex = {
-        // Regex::new(r#""^(?:(\d* \w{2}),? ?)*(.*)?\n"#).unwrap()
-        Regex::new(r#"^(?:([\d,]* \w{2})(?:, )?)*"#).unwrap()
-    };
-}
-
 #[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, serde::Serialize, serde::Deserialize)]
 pub struct Item {
     pub name: String,
-    pub pfs: String,
+    pub pfs: PfsStatus,
     pub source: String,
-    pub rarity: String,
-    pub r#trait: String,
-    pub item_category: String,
+    pub rarity: Rarity,
+    /// Older saves stored the comma separated trait column as `trait`
+    #[serde(alias = "trait", deserialize_with = "deserialize_traits")]
+    pub traits: Vec<Trait>,
+    pub item_category: ItemCategory,
     pub item_subcategory: String,
     pub level: i32,
     pub price: Option<Price>,
-    pub bulk: String,
+    pub bulk: Bulk,
     pub usage: String,
     pub spoilers: String,
 }
 
-impl From<DbItem> for Item {
-    fn from(value: DbItem) -> Self {
-        Self {
+impl Item {
+    /// Whether the item has the trait called `name` (case insensitive)
+    pub fn has_trait(&self, name: &str) -> bool {
+        self.traits.iter().any(|t| t.is(name))
+    }
+}
+
+impl TryFrom<DbItem> for Item {
+    type Error = anyhow::Error;
+
+    fn try_from(value: DbItem) -> Result<Self> {
+        let price = Price::parse(&value.price)
+            .with_context(|| format!("Invalid price for {}: {}", value.name, value.price))?;
+        Ok(Self {
+            traits: parse_traits(&value.r#trait),
+            bulk: Bulk::from(value.bulk.as_str()),
             name: value.name,
             pfs: value.pfs,
             source: value.source,
             rarity: value.rarity,
-            r#trait: value.r#trait,
             item_category: value.item_category,
             item_subcategory: value.item_subcategory,
             level: value.level,
-            price: Price::parse(&value.price).unwrap_or_else(|e| {
-                tracing::error!("{:?}", e);
-                tracing::error!("{:?}", value.price);
-                panic!();
-            }),
-            bulk: value.bulk,
+            price,
             usage: value.usage,
             spoilers: value.spoilers,
+        })
+    }
+}
+
+fn deserialize_traits<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Vec<Trait>, D::Error> {
+    #[derive(serde::Deserialize)]
+    #[serde(untagged)]
+    enum Repr {
+        List(Vec<Trait>),
+        Column(String),
+    }
+
+    Ok(
+        match <Repr as serde::Deserialize>::deserialize(deserializer)? {
+            Repr::List(traits) => traits,
+            Repr::Column(column) => parse_traits(&column),
+        },
+    )
+}
+
+/// Split a comma separated trait column into traits
+pub fn parse_traits(column: &str) -> Vec<Trait> {
+    column
+        .split(',')
+        .map(Trait::new)
+        .filter(|t| !t.name().is_empty())
+        .collect()
+}
+
+/// An item trait as it appears on AoN, e.g. "Magical" or "Deadly d8"
+#[derive(
+    Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, serde::Serialize, serde::Deserialize,
+)]
+#[serde(transparent)]
+pub struct Trait(String);
+
+impl Trait {
+    /// Surrounding whitespace is trimmed and inner whitespace collapsed
+    pub fn new<S: AsRef<str>>(name: S) -> Self {
+        Self(
+            name.as_ref()
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" "),
+        )
+    }
+
+    pub fn name(&self) -> &str {
+        &self.0
+    }
+
+    /// Whether this is the trait called `name` (case insensitive)
+    pub fn is(&self, name: &str) -> bool {
+        self.0.eq_ignore_ascii_case(name.trim())
+    }
+}
+
+impl std::fmt::Display for Trait {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// How cumbersome an item is to carry
+#[derive(
+    Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, serde::Serialize, serde::Deserialize,
+)]
+#[serde(from = "String", into = "String")]
+pub enum Bulk {
+    /// Written "—" on AoN, or left blank
+    Negligible,
+    /// Written "L"
+    Light,
+    Value(u32),
+    /// The item's bulk while it isn't activated, e.g. "L when not activated"
+    WhenNotActivated(Box<Bulk>),
+    /// Bulk that doesn't fit the other variants, e.g. "varies", kept as written
+    Other(String),
+}
+
+impl Bulk {
+    /// The bulk as a number, where light bulk is 0.1
+    /// Returns `None` for bulk that doesn't start with a known amount
+    pub fn value(&self) -> Option<f64> {
+        match self {
+            Bulk::Negligible => Some(0.0),
+            Bulk::Light => Some(0.1),
+            Bulk::Value(n) => Some(*n as f64),
+            Bulk::WhenNotActivated(bulk) => bulk.value(),
+            Bulk::Other(text) => match text.split_whitespace().next()? {
+                "L" => Some(0.1),
+                amount => amount.parse().ok(),
+            },
+        }
+    }
+}
+
+impl From<&str> for Bulk {
+    fn from(value: &str) -> Self {
+        let value = value.trim();
+        for suffix in ["(when not activated)", "when not activated", "inactivated"] {
+            if let Some(bulk) = value.strip_suffix(suffix) {
+                return Bulk::WhenNotActivated(Box::new(Bulk::from(bulk)));
+            }
+        }
+        match value {
+            "" | "—" | "-" => Bulk::Negligible,
+            "L" => Bulk::Light,
+            _ => match value.parse() {
+                Ok(n) => Bulk::Value(n),
+                Err(_) => Bulk::Other(value.to_string()),
+            },
+        }
+    }
+}
+
+impl From<String> for Bulk {
+    fn from(value: String) -> Self {
+        Bulk::from(value.as_str())
+    }
+}
+
+impl From<Bulk> for String {
+    fn from(value: Bulk) -> Self {
+        value.to_string()
+    }
+}
+
+impl std::str::FromStr for Bulk {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(Bulk::from(s))
+    }
+}
+
+impl std::fmt::Display for Bulk {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Bulk::Negligible => write!(f, "—"),
+            Bulk::Light => write!(f, "L"),
+            Bulk::Value(n) => write!(f, "{}", n),
+            Bulk::WhenNotActivated(bulk) => write!(f, "{} when not activated", bulk),
+            Bulk::Other(text) => write!(f, "{}", text),
         }
     }
 }
 
+/// Whether an item can be bought in Pathfinder Society play
 #[derive(
-    Debug,
-    Clone,
-    Eq,
-    PartialEq,
-    Ord,
-    PartialOrd,
-    serde::Serialize,
-    serde::Deserialize,
-    sqlx::FromRow,
-    sqlx::Decode,
-    sqlx::Type,
+    Debug, Default, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash, enum_iterator::Sequence,
 )]
+pub enum PfsStatus {
+    Standard,
+    Limited,
+    Restricted,
+    /// No PFS availability is listed on AoN
+    #[default]
+    Unlisted,
+}
+
+impl AsRef<str> for PfsStatus {
+    fn as_ref(&self) -> &str {
+        match self {
+            PfsStatus::Standard => "Standard",
+            PfsStatus::Limited => "Limited",
+            PfsStatus::Restricted => "Restricted",
+            PfsStatus::Unlisted => "",
+        }
+    }
+}
+
+impl std::str::FromStr for PfsStatus {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        enum_iterator::all::<PfsStatus>()
+            .find(|p| normalize_name(p.as_ref()) == normalize_name(s))
+            .with_context(|| format!("Unknown PFS status: {}", s))
+    }
+}
+
+/// A price as written on AoN, e.g. "1 sp, 7 cp (per 1,000 bricks)"
+///
+/// Prices are some coins followed by an optional qualifier in brackets. The
+/// qualifiers this understands are split out into their own fields; the
+/// qualifier text is always kept as written.
+///
+/// Prices are saved as their text and parsed again when loaded.
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd)]
 pub struct Price {
     text: String,
     pp: i32,
     gp: i32,
     sp: i32,
     cp: i32,
+    /// How many items the price buys, e.g. "(price for 10)"
+    batch_size: Option<u32>,
+    /// What the price pays for, e.g. "(per 1,000 bricks)"
+    per_unit: Option<PerUnit>,
+    /// How long the price lasts, e.g. "(1 week)" or "(per day)"
+    duration: Option<PriceDuration>,
+    /// Extra cost in cp for each Bulk, e.g. "(+120 gp per Bulk)"
+    per_bulk_cp: Option<i32>,
+    /// The text inside the brackets after the coins
+    qualifier: Option<String>,
 }
 
-impl<S: AsRef<str>> From<S> for Price {
-    fn from(value: S) -> Self {
-        Self::parse(value)
-            .context("Failed to parse price")
-            .unwrap()
-            .unwrap()
+/// Some amount of a unit a price pays for, e.g. 1,000 bricks
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, serde::Serialize, serde::Deserialize)]
+pub struct PerUnit {
+    pub amount: u32,
+    pub unit: String,
+}
+
+/// How long a price lasts, e.g. 1 week of rations
+#[derive(
+    Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, serde::Serialize, serde::Deserialize,
+)]
+pub struct PriceDuration {
+    pub amount: u32,
+    pub unit: TimeUnit,
+}
+
+#[derive(
+    Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, serde::Serialize, serde::Deserialize,
+)]
+pub enum TimeUnit {
+    Hour,
+    Day,
+    Week,
+}
+
+impl std::str::FromStr for TimeUnit {
+    type Err = ();
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "hour" | "hours" => Ok(TimeUnit::Hour),
+            "day" | "days" => Ok(TimeUnit::Day),
+            "week" | "weeks" => Ok(TimeUnit::Week),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Why a price couldn't be parsed
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum PriceError {
+    /// There was no price to parse
+    Empty,
+    /// An amount of coins isn't a number, e.g. "1,00 gp"
+    InvalidAmount(String),
+    /// The price is too large to count in cp
+    Overflow(String),
+    /// An amount isn't followed by a coin, e.g. "12"
+    MissingCoin(String),
+    /// A coin other than pp, gp, sp or cp
+    UnknownCoin(String),
+    /// The same coin is given twice, e.g. "1 gp, 2 gp"
+    DuplicateCoin(String),
+    /// The qualifier's brackets don't match up, e.g. "5 gp (per day"
+    UnbalancedBrackets(String),
+    /// Text after the coins that isn't a qualifier in brackets
+    TrailingText(String),
+}
+
+impl std::fmt::Display for PriceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PriceError::Empty => write!(f, "Price is empty"),
+            PriceError::InvalidAmount(s) => write!(f, "Invalid amount of coins: {}", s),
+            PriceError::Overflow(s) => write!(f, "Price is too large: {}", s),
+            PriceError::MissingCoin(s) => write!(f, "Amount has no coin: {}", s),
+            PriceError::UnknownCoin(s) => write!(f, "Unrecognized coin: {}", s),
+            PriceError::DuplicateCoin(s) => write!(f, "Coin given more than once: {}", s),
+            PriceError::UnbalancedBrackets(s) => write!(f, "Unbalanced brackets: {}", s),
+            PriceError::TrailingText(s) => write!(f, "Unexpected text after price: {}", s),
+        }
+    }
+}
+
+impl std::error::Error for PriceError {}
+
+impl std::str::FromStr for Price {
+    type Err = PriceError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Price::parse(s)?.ok_or(PriceError::Empty)
+    }
+}
+
+impl serde::Serialize for Price {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.text)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Price {
+    /// Accepts the text of the price and the struct older versions saved
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        #[derive(serde::Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Text(String),
+            Saved { text: String },
+        }
+
+        let (Repr::Text(text) | Repr::Saved { text }) = Repr::deserialize(deserializer)?;
+        text.parse().map_err(serde::de::Error::custom)
     }
 }
 
 impl Price {
-    pub fn parse<S: AsRef<str>>(input: S) -> Result<Option<Price>> {
-        if input.as_ref().is_empty() {
+    /// Parse a price as written on AoN
+    /// Returns `None` for items without a price in coins, i.e. an empty price
+    /// or one that's only a qualifier like "(Varies)"
+    pub fn parse<S: AsRef<str>>(input: S) -> std::result::Result<Option<Price>, PriceError> {
+        let text = input.as_ref();
+        let trimmed = text.trim();
+        if trimmed.is_empty() {
             return Ok(None);
         }
-        let mut pp = 0;
-        let mut gp = 0;
-        let mut sp = 0;
-        let mut cp = 0;
-
-        let captures = PRICE_RE
-            .captures(input.as_ref())
-            .context("Failed to find price")?;
-
-        for c in captures.iter().flatten() {
-            if c.is_empty() {
-                continue;
-            }
-            let (amount, coin) = c.as_str().split_once(" ").context("Failed to split text")?;
-            let Some(coin) = coin.get(0..2) else { continue };
-            let amount = amount
-                .chars()
-                .filter(|c| c.is_numeric())
-                .collect::<String>()
-                .parse::<i32>()
-                .context("Failed to parse amount as i32")?;
-            match coin {
-                "pp" => pp = amount,
-                "gp" => gp = amount,
-                "sp" => sp = amount,
-                "cp" => cp = amount,
-                _ => {
-                    #[cfg(not(test))]
-                    tracing::error!("Unrecognized coin: {}", coin);
-                    #[cfg(test)]
-                    panic!("Unrecognized coin: {}", coin);
-                }
-            }
-        }
 
-        Ok(Some(Price {
-            text: input.as_ref().to_string(),
+        let (coins, rest) = parse_coins(trimmed)?;
+        let rest = rest.trim();
+        let qualifier = if rest.is_empty() {
+            None
+        } else {
+            Some(strip_brackets(rest)?)
+        };
+        let Some(coins) = coins else {
+            return match qualifier {
+                Some(_) => Ok(None),
+                None => Err(PriceError::InvalidAmount(trimmed.to_string())),
+            };
+        };
+        let [pp, gp, sp, cp] = coins;
+        let mut price = Price {
+            text: text.to_string(),
             pp,
             gp,
             sp,
             cp,
-        }))
+            batch_size: None,
+            per_unit: None,
+            duration: None,
+            per_bulk_cp: None,
+            qualifier: qualifier.map(|q| q.to_string()),
+        };
+        price.as_cp_checked()?;
+        if let Some(qualifier) = qualifier {
+            price.read_qualifier(qualifier)?;
+        }
+
+        Ok(Some(price))
     }
 
+    /// Fill in the structured fields from the qualifier
+    /// Qualifiers that aren't understood are only kept as text
+    fn read_qualifier(&mut self, qualifier: &str) -> std::result::Result<(), PriceError> {
+        let q = qualifier.trim();
+        if let Some(rest) = strip_prefix_ignore_case(q, "price for ") {
+            self.batch_size = amount_and_unit(rest).map(|(amount, _)| amount);
+        } else if let Some(rest) = strip_prefix_ignore_case(q, "per ") {
+            let (amount, unit) = amount_and_unit(rest).unwrap_or((1, rest.trim()));
+            match unit.parse::<TimeUnit>() {
+                Ok(unit) => self.duration = Some(PriceDuration { amount, unit }),
+                Err(_) => {
+                    self.per_unit = Some(PerUnit {
+                        amount,
+                        unit: bulk_typo(unit).to_string(),
+                    })
+                }
+            }
+        } else if let Some(rest) = q
+            .strip_prefix('+')
+            .or_else(|| strip_prefix_ignore_case(q, "plus "))
+        {
+            let (coins, rest) = parse_coins(rest.trim())?;
+            let per = rest.trim().strip_prefix("per ").map(bulk_typo);
+            if let (Some(coins), Some("Bulk")) = (coins, per) {
+                let cp =
+                    coins_to_cp(coins).ok_or_else(|| PriceError::Overflow(self.text.clone()))?;
+                self.per_bulk_cp = Some(cp);
+            }
+        } else if let Some((amount, unit)) = amount_and_unit(q) {
+            match unit.parse::<TimeUnit>() {
+                Ok(unit) => self.duration = Some(PriceDuration { amount, unit }),
+                Err(_) if unit.eq_ignore_ascii_case("pieces") => self.batch_size = Some(amount),
+                Err(_) if !unit.is_empty() => {
+                    self.per_unit = Some(PerUnit {
+                        amount,
+                        unit: unit.to_string(),
+                    })
+                }
+                Err(_) => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn as_cp_checked(&self) -> std::result::Result<i32, PriceError> {
+        coins_to_cp([self.pp, self.gp, self.sp, self.cp])
+            .ok_or_else(|| PriceError::Overflow(self.text.clone()))
+    }
+
+    /// The price in cp as written, which is the price of a whole batch for
+    /// items sold in batches, see `batch`
     pub fn as_cp(&self) -> i32 {
         let pp = self.pp * 1000;
         let gp = self.gp * 100;
@@ -136,6 +470,198 @@ impl Price {
 
         pp + gp + sp + self.cp
     }
+
+    /// The price split into coins as written, as `[pp, gp, sp, cp]`
+    pub fn coins(&self) -> [i32; 4] {
+        [self.pp, self.gp, self.sp, self.cp]
+    }
+
+    pub fn batch_size(&self) -> Option<u32> {
+        self.batch_size
+    }
+
+    /// How many items the price buys, 1 unless the item is sold in batches
+    pub fn batch(&self) -> u32 {
+        self.batch_size.unwrap_or(1).max(1)
+    }
+
+    /// The price of a single item in cp, which can be a fraction of a cp for
+    /// cheap items sold in batches
+    pub fn unit_cp(&self) -> f64 {
+        self.as_cp() as f64 / self.batch() as f64
+    }
+
+    /// The price of `quantity` items in cp, rounded up to a whole cp
+    pub fn cost_cp(&self, quantity: u32) -> i32 {
+        let batch = self.batch() as i64;
+        let cp = (self.as_cp() as i64 * quantity as i64 + batch - 1) / batch;
+        // too much for anyone to pay, rather than wrapping round to a bargain
+        cp.min(i32::MAX as i64) as i32
+    }
+
+    pub fn per_unit(&self) -> Option<&PerUnit> {
+        self.per_unit.as_ref()
+    }
+
+    pub fn duration(&self) -> Option<PriceDuration> {
+        self.duration
+    }
+
+    pub fn per_bulk_cp(&self) -> Option<i32> {
+        self.per_bulk_cp
+    }
+
+    pub fn qualifier(&self) -> Option<&str> {
+        self.qualifier.as_deref()
+    }
+}
+
+/// Parse coins like "1,500 gp" or "1 sp, 7 cp" from the start of `s`
+/// Returns the coins as `[pp, gp, sp, cp]`, or `None` if `s` doesn't start
+/// with an amount, and the text after them
+fn parse_coins(s: &str) -> std::result::Result<(Option<[i32; 4]>, &str), PriceError> {
+    let mut coins = [None; 4];
+    let mut rest = s;
+    loop {
+        let amount_len = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == ','))
+            .unwrap_or(rest.len());
+        if amount_len == 0 {
+            break;
+        }
+        let amount = parse_amount(&rest[..amount_len])?;
+
+        let after = rest[amount_len..].trim_start();
+        let coin_len = after
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(after.len());
+        let coin = &after[..coin_len];
+        let index = match coin {
+            "pp" => 0,
+            "gp" => 1,
+            "sp" => 2,
+            "cp" => 3,
+            "" => return Err(PriceError::MissingCoin(s.to_string())),
+            _ => return Err(PriceError::UnknownCoin(coin.to_string())),
+        };
+        if coins[index].is_some() {
+            return Err(PriceError::DuplicateCoin(s.to_string()));
+        }
+        coins[index] = Some(amount);
+        rest = &after[coin_len..];
+
+        // more coins follow a comma, e.g. "1 gp, 5 sp"
+        match rest.trim_start().strip_prefix(',') {
+            Some(next) if next.trim_start().starts_with(|c: char| c.is_ascii_digit()) => {
+                rest = next.trim_start();
+            }
+            _ => break,
+        }
+    }
+
+    if coins.iter().all(|c| c.is_none()) {
+        return Ok((None, rest));
+    }
+    Ok((Some(coins.map(|c| c.unwrap_or(0))), rest))
+}
+
+/// The value in cp of `[pp, gp, sp, cp]`, or `None` if it doesn't fit
+fn coins_to_cp(coins: [i32; 4]) -> Option<i32> {
+    coins
+        .iter()
+        .zip([1000, 100, 10, 1])
+        .try_fold(0_i32, |total, (amount, value)| {
+            amount.checked_mul(value)?.checked_add(total)
+        })
+}
+
+/// Parse an amount that may use commas between thousands, e.g. "1,000"
+fn parse_amount(s: &str) -> std::result::Result<i32, PriceError> {
+    let mut groups = s.split(',');
+    let first = groups.next().unwrap_or_default();
+    let valid =
+        !first.is_empty() && (first.len() <= 3 || !s.contains(',')) && groups.all(|g| g.len() == 3);
+    if !valid {
+        return Err(PriceError::InvalidAmount(s.to_string()));
+    }
+    s.replace(',', "")
+        .parse()
+        .map_err(|_| PriceError::Overflow(s.to_string()))
+}
+
+/// The text inside a qualifier, e.g. "per day" from "(per day)"
+/// Doubled brackets like "((200 gp when nonmagical))" are stripped too
+fn strip_brackets(s: &str) -> std::result::Result<&str, PriceError> {
+    if !s.starts_with('(') {
+        return Err(PriceError::TrailingText(s.to_string()));
+    }
+    let mut inner = s;
+    while inner.starts_with('(') {
+        let mut depth = 0;
+        let mut close = None;
+        for (i, c) in inner.char_indices() {
+            match c {
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                _ => {}
+            }
+            if depth == 0 {
+                close = Some(i);
+                break;
+            }
+        }
+        match close {
+            Some(i) if i == inner.len() - 1 => inner = inner[1..i].trim(),
+            Some(_) if inner == s => return Err(PriceError::TrailingText(s.to_string())),
+            Some(_) => break,
+            None => return Err(PriceError::UnbalancedBrackets(s.to_string())),
+        }
+    }
+    if inner.matches('(').count() != inner.matches(')').count() {
+        return Err(PriceError::UnbalancedBrackets(s.to_string()));
+    }
+
+    Ok(inner)
+}
+
+/// Split "1,000 bricks" into `(1000, "bricks")`
+fn amount_and_unit(s: &str) -> Option<(u32, &str)> {
+    let s = s.trim();
+    let (amount, unit) = s.split_once(' ').unwrap_or((s, ""));
+    let amount = parse_amount(amount).ok()?;
+    Some((u32::try_from(amount).ok()?, unit.trim()))
+}
+
+fn strip_prefix_ignore_case<'a>(s: &'a str, prefix: &str) -> Option<&'a str> {
+    let head = s.get(..prefix.len())?;
+    head.eq_ignore_ascii_case(prefix)
+        .then(|| &s[prefix.len()..])
+}
+
+/// A few AoN prices spell Bulk as "Buk"
+fn bulk_typo(unit: &str) -> &str {
+    match unit.trim() {
+        "Buk" | "Bulk" | "bulk" => "Bulk",
+        unit => unit,
+    }
+}
+
+/// Format an amount in cp using the largest coins that fit, e.g. "12 gp, 5 sp"
+/// Platinum is never used since prices on AoN are given in gp
+pub fn format_cp(cp: i32) -> String {
+    if cp == 0 {
+        return "0 cp".to_string();
+    }
+    let sign = if cp < 0 { "-" } else { "" };
+    let cp = cp.abs();
+    let coins = [(cp / 100, "gp"), (cp / 10 % 10, "sp"), (cp % 10, "cp")];
+    let text = coins
+        .iter()
+        .filter(|(amount, _)| *amount > 0)
+        .map(|(amount, coin)| format!("{} {}", amount, coin))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!("{}{}", sign, text)
 }
 
 impl std::fmt::Display for Price {
@@ -144,123 +670,91 @@ impl std::fmt::Display for Price {
     }
 }
 
-#[derive(
-    enum_derived::Rand,
-    Debug,
-    Clone,
-    Copy,
-    Eq,
-    PartialEq,
-    Ord,
-    PartialOrd,
-    enum_iterator::Sequence,
-    Hash,
-)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, enum_iterator::Sequence, Hash)]
 pub enum ItemCategory {
-    #[weight(0)]
     Vehicles,
-
-    #[weight(60)]
     Weapons,
-
-    #[weight(25)]
     AdventuringGear,
-
-    #[weight(25)]
     AlchemicalItems,
-
-    #[weight(0)]
     Services,
-
-    #[weight(25)]
     TradeGoods,
-
-    #[weight(0)]
     AnimalsandGear,
-
-    #[weight(25)]
     Materials,
-
-    #[weight(60)]
     Armor,
-
     AssistiveItems,
-
     Adjustments,
-
-    #[weight(25)]
     Shields,
-
     Other,
-
     Customizations,
-
-    #[weight(50)]
     Consumables,
-
     Snares,
-
-    #[weight(25)]
     HeldItems,
-
-    #[weight(25)]
     WornItems,
-
-    #[weight(12)]
     Grafts,
-
-    #[weight(4)]
     Tattoos,
-
-    #[weight(0)]
     SiegeWeapons,
-
-    #[weight(10)]
     Runes,
-
-    #[weight(0)]
     Artifacts,
-
-    #[weight(0)]
     CursedItems,
-
-    #[weight(10)]
     Spellhearts,
-
-    #[weight(60)]
     Wands,
-
-    #[weight(60)]
     Staves,
-
-    #[weight(0)]
     IntelligentItems,
-
-    #[weight(0)]
     Contracts,
-
-    #[weight(0)]
     Relics,
-
-    #[weight(10)]
     Grimoires,
-
-    #[weight(3)]
     Structures,
-
-    #[weight(3)]
     Censer,
-
-    #[weight(3)]
     Figurehead,
-
-    #[weight(0)]
     BlightedBoons,
-
-    #[weight(0)]
     HighTech,
 }
 
+impl ItemCategory {
+    /// How likely a generic merchant is to stock this category, relative to
+    /// the others
+    pub fn default_weight(&self) -> u32 {
+        match self {
+            ItemCategory::Vehicles => 0,
+            ItemCategory::Weapons => 60,
+            ItemCategory::AdventuringGear => 25,
+            ItemCategory::AlchemicalItems => 25,
+            ItemCategory::Services => 0,
+            ItemCategory::TradeGoods => 25,
+            ItemCategory::AnimalsandGear => 0,
+            ItemCategory::Materials => 25,
+            ItemCategory::Armor => 60,
+            ItemCategory::AssistiveItems => 1,
+            ItemCategory::Adjustments => 1,
+            ItemCategory::Shields => 25,
+            ItemCategory::Other => 1,
+            ItemCategory::Customizations => 1,
+            ItemCategory::Consumables => 50,
+            ItemCategory::Snares => 1,
+            ItemCategory::HeldItems => 25,
+            ItemCategory::WornItems => 25,
+            ItemCategory::Grafts => 12,
+            ItemCategory::Tattoos => 4,
+            ItemCategory::SiegeWeapons => 0,
+            ItemCategory::Runes => 10,
+            ItemCategory::Artifacts => 0,
+            ItemCategory::CursedItems => 0,
+            ItemCategory::Spellhearts => 10,
+            ItemCategory::Wands => 60,
+            ItemCategory::Staves => 60,
+            ItemCategory::IntelligentItems => 0,
+            ItemCategory::Contracts => 0,
+            ItemCategory::Relics => 0,
+            ItemCategory::Grimoires => 10,
+            ItemCategory::Structures => 3,
+            ItemCategory::Censer => 3,
+            ItemCategory::Figurehead => 3,
+            ItemCategory::BlightedBoons => 0,
+            ItemCategory::HighTech => 0,
+        }
+    }
+}
+
 impl AsRef<str> for ItemCategory {
     fn as_ref(&self) -> &str {
         match self {
@@ -299,16 +793,28 @@ impl AsRef<str> for ItemCategory {
             ItemCategory::Censer => "Censer",
             ItemCategory::Figurehead => "Figurehead",
             ItemCategory::BlightedBoons => "Blighted Boons",
-            ItemCategory::HighTech => "HighTech",
+            ItemCategory::HighTech => "High-Tech",
         }
     }
 }
 
-#[derive(enum_derived::Rand)]
+#[derive(
+    enum_derived::Rand,
+    Debug,
+    Clone,
+    Copy,
+    Eq,
+    PartialEq,
+    Ord,
+    PartialOrd,
+    Hash,
+    enum_iterator::Sequence,
+)]
 pub enum Rarity {
     Common,
     Uncommon,
     Rare,
+    Unique,
 }
 
 impl AsRef<str> for Rarity {
@@ -317,13 +823,113 @@ impl AsRef<str> for Rarity {
             Rarity::Common => "Common",
             Rarity::Uncommon => "Uncommon",
             Rarity::Rare => "Rare",
+            Rarity::Unique => "Unique",
         }
     }
 }
 
+impl std::str::FromStr for Rarity {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        enum_iterator::all::<Rarity>()
+            .find(|r| normalize_name(r.as_ref()) == normalize_name(s))
+            .with_context(|| format!("Unknown rarity: {}", s))
+    }
+}
+
+impl std::str::FromStr for ItemCategory {
+    type Err = anyhow::Error;
+
+    /// Accepts the category as it appears on AoN, ignoring case and punctuation
+    fn from_str(s: &str) -> Result<Self> {
+        enum_iterator::all::<ItemCategory>()
+            .find(|c| normalize_name(c.as_ref()) == normalize_name(s))
+            .with_context(|| format!("Unknown item category: {}", s))
+    }
+}
+
+/// Serde and sqlx support for enums stored as their AoN text
+/// Reading accepts anything `FromStr` does, so both "Held Items" and the
+/// variant name "HeldItems" work
+macro_rules! aon_text {
+    ($ty:ty) => {
+        impl std::fmt::Display for $ty {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write!(f, "{}", self.as_ref())
+            }
+        }
+
+        impl serde::Serialize for $ty {
+            fn serialize<S: serde::Serializer>(
+                &self,
+                serializer: S,
+            ) -> std::result::Result<S::Ok, S::Error> {
+                serializer.serialize_str(self.as_ref())
+            }
+        }
+
+        impl<'de> serde::Deserialize<'de> for $ty {
+            fn deserialize<D: serde::Deserializer<'de>>(
+                deserializer: D,
+            ) -> std::result::Result<Self, D::Error> {
+                let s = String::deserialize(deserializer)?;
+                s.parse().map_err(serde::de::Error::custom)
+            }
+        }
+
+        impl sqlx::Type<sqlx::Sqlite> for $ty {
+            fn type_info() -> sqlx::sqlite::SqliteTypeInfo {
+                <str as sqlx::Type<sqlx::Sqlite>>::type_info()
+            }
+
+            fn compatible(ty: &sqlx::sqlite::SqliteTypeInfo) -> bool {
+                <str as sqlx::Type<sqlx::Sqlite>>::compatible(ty)
+            }
+        }
+
+        impl<'q> sqlx::Encode<'q, sqlx::Sqlite> for $ty {
+            fn encode_by_ref(
+                &self,
+                buf: &mut Vec<sqlx::sqlite::SqliteArgumentValue<'q>>,
+            ) -> std::result::Result<sqlx::encode::IsNull, sqlx::error::BoxDynError> {
+                <String as sqlx::Encode<'q, sqlx::Sqlite>>::encode(self.as_ref().to_string(), buf)
+            }
+        }
+
+        impl<'r> sqlx::Decode<'r, sqlx::Sqlite> for $ty {
+            fn decode(
+                value: sqlx::sqlite::SqliteValueRef<'r>,
+            ) -> std::result::Result<Self, sqlx::error::BoxDynError> {
+                let s = <&str as sqlx::Decode<'r, sqlx::Sqlite>>::decode(value)?;
+                Ok(s.parse::<$ty>()?)
+            }
+        }
+    };
+}
+
+aon_text!(ItemCategory);
+aon_text!(Rarity);
+aon_text!(PfsStatus);
+
+/// Lowercase and strip everything but letters and numbers so user input like
+/// "held-items" matches "Held Items"
+fn normalize_name(s: &str) -> String {
+    s.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(|c| c.to_lowercase())
+        .collect()
+}
+
 mod tests {
     #![allow(unused)]
-    use crate::item::Price;
+    use crate::{
+        database::{DbItem, EQUIPMENT_CSV},
+        item::{
+            parse_traits, Bulk, ItemCategory, PerUnit, PfsStatus, Price, PriceDuration, PriceError,
+            Rarity, TimeUnit, Trait,
+        },
+    };
 
     #[test]
     fn parse_prices() {
@@ -344,5 +950,131 @@ mod tests {
             assert_eq!(price.sp, expected[2]);
             assert_eq!(price.cp, expected[3]);
         }
+
+        let price = Price::parse("1 sp (price for 10)").unwrap().unwrap();
+        assert_eq!(price.batch_size(), Some(10));
+        let price = Price::parse("1 cp (10 pieces)").unwrap().unwrap();
+        assert_eq!(price.batch_size(), Some(10));
+        let price = Price::parse("1 sp, 7 cp (per 1,000 bricks)")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            price.per_unit(),
+            Some(&PerUnit {
+                amount: 1000,
+                unit: "bricks".to_string()
+            })
+        );
+        let price = Price::parse("4 sp (1 week)").unwrap().unwrap();
+        assert_eq!(
+            price.duration(),
+            Some(PriceDuration {
+                amount: 1,
+                unit: TimeUnit::Week
+            })
+        );
+        let price = Price::parse("10 gp (per day)").unwrap().unwrap();
+        assert_eq!(price.duration().map(|d| d.unit), Some(TimeUnit::Day));
+        for input in [
+            "1,200 gp (+120 gp per Bulk)",
+            "1,200 gp (+120 gp per Buk)",
+            "1,200 gp (plus 120 gp per Bulk)",
+        ] {
+            let price = Price::parse(input).unwrap().unwrap();
+            assert_eq!(price.per_bulk_cp(), Some(12000), "{}", input);
+        }
+        let price = Price::parse("2,500 gp ((1,000 gp when nonmagical))")
+            .unwrap()
+            .unwrap();
+        assert_eq!(price.qualifier(), Some("1,000 gp when nonmagical"));
+        assert_eq!(price.as_cp(), 250000);
+
+        assert_eq!(Price::parse(""), Ok(None));
+        assert_eq!(Price::parse(" (Varies)"), Ok(None));
+        let errors = [
+            ("12", PriceError::MissingCoin("12".to_string())),
+            ("12 ep", PriceError::UnknownCoin("ep".to_string())),
+            ("1,00 gp", PriceError::InvalidAmount("1,00".to_string())),
+            (
+                "1 gp, 2 gp",
+                PriceError::DuplicateCoin("1 gp, 2 gp".to_string()),
+            ),
+            (
+                "5 gp (per day",
+                PriceError::UnbalancedBrackets("(per day".to_string()),
+            ),
+            ("5 gp each", PriceError::TrailingText("each".to_string())),
+            (
+                "9,999,999 pp",
+                PriceError::Overflow("9,999,999 pp".to_string()),
+            ),
+            (
+                "1 gp (+9,999,999 pp per Bulk)",
+                PriceError::Overflow("1 gp (+9,999,999 pp per Bulk)".to_string()),
+            ),
+        ];
+        for (input, expected) in errors {
+            assert_eq!(Price::parse(input), Err(expected), "{}", input);
+        }
+    }
+
+    #[test]
+    fn parse_every_bundled_price() {
+        let mut rdr = csv::Reader::from_reader(EQUIPMENT_CSV.as_bytes());
+        let mut count = 0;
+        for row in rdr.deserialize::<DbItem>() {
+            let row = row.unwrap();
+            if let Err(e) = Price::parse(&row.price) {
+                panic!("{}: {:?} ({})", row.name, row.price, e);
+            }
+            count += 1;
+        }
+        assert!(count > 4900);
+    }
+
+    #[test]
+    fn parse_typed_columns() {
+        let input = [
+            ("L", Bulk::Light),
+            ("", Bulk::Negligible),
+            ("—", Bulk::Negligible),
+            ("16", Bulk::Value(16)),
+            (
+                "L when not activated",
+                Bulk::WhenNotActivated(Box::new(Bulk::Light)),
+            ),
+            (
+                " when not activated",
+                Bulk::WhenNotActivated(Box::new(Bulk::Negligible)),
+            ),
+            (
+                "1 (when not activated)",
+                Bulk::WhenNotActivated(Box::new(Bulk::Value(1))),
+            ),
+            (" varies", Bulk::Other("varies".to_string())),
+        ];
+        for (input, expected) in input {
+            let bulk = Bulk::from(input);
+            assert_eq!(bulk, expected);
+            assert_eq!(Bulk::from(bulk.to_string().as_str()), expected);
+        }
+
+        let traits = parse_traits("Magical, Thrown \u{a0}10 ft., ,Uncommon");
+        assert_eq!(traits.len(), 3);
+        assert_eq!(traits[1], Trait::new("Thrown 10 ft."));
+        assert!(traits[2].is("uncommon"));
+
+        assert_eq!("unique".parse::<Rarity>().unwrap(), Rarity::Unique);
+        assert_eq!("".parse::<PfsStatus>().unwrap(), PfsStatus::Unlisted);
+        assert_eq!(
+            "High-Tech".parse::<ItemCategory>().unwrap(),
+            ItemCategory::HighTech
+        );
+
+        // categories are written as on AoN, but variant names are still read
+        let json = serde_json::to_string(&ItemCategory::HeldItems).unwrap();
+        assert_eq!(json, "\"Held Items\"");
+        let category: ItemCategory = serde_json::from_str("\"HeldItems\"").unwrap();
+        assert_eq!(category, ItemCategory::HeldItems);
     }
 }
@@ -0,0 +1,293 @@
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Result};
+
+use crate::{
+    item::ItemCategory,
+    profile::{
+        GuaranteedStock, MerchantProfile, StockFilter, DEFAULT_FILL_TARGET, DEFAULT_FILL_TOLERANCE,
+        DEFAULT_LEVEL_FALLOFF, DEFAULT_MAX_FILL_ATTEMPTS, DEFAULT_MAX_LEVEL_OFFSET,
+        DEFAULT_MIN_LEVEL_OFFSET, DEFAULT_RARE_CHANCE, DEFAULT_SUBCATEGORY_WEIGHT,
+        DEFAULT_UNCOMMON_CHANCE, DEFAULT_UNIQUE_CHANCE,
+    },
+};
+
+/// Relative weight of a category when picking what to stock next
+pub type CategoryWeight = (ItemCategory, u32);
+
+/// Relative weight of a subcategory within its category
+/// Subcategory must match string exactly as it appears on AoN
+pub type SubcategoryWeight = (ItemCategory, &'static str, u32);
+
+/// The type of shop a merchant runs, which determines what they tend to stock
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    Eq,
+    PartialEq,
+    Ord,
+    PartialOrd,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+    enum_iterator::Sequence,
+)]
+pub enum MerchantKind {
+    /// Stocks a bit of everything using the default category weights
+    #[default]
+    Generic,
+    Blacksmith,
+    Alchemist,
+    Arcanist,
+    GeneralStore,
+}
+
+impl MerchantKind {
+    /// Category weights for this kind of merchant
+    pub fn category_weights(&self) -> Vec<CategoryWeight> {
+        use ItemCategory::*;
+        match self {
+            MerchantKind::Generic => enum_iterator::all::<ItemCategory>()
+                .map(|c| (c, c.default_weight()))
+                .collect(),
+            MerchantKind::Blacksmith => vec![
+                (Weapons, 60),
+                (Armor, 60),
+                (Shields, 40),
+                (Materials, 25),
+                (Runes, 10),
+                (AdventuringGear, 10),
+                (TradeGoods, 5),
+            ],
+            MerchantKind::Alchemist => vec![
+                (AlchemicalItems, 80),
+                (Consumables, 30),
+                (Materials, 10),
+                (AdventuringGear, 5),
+            ],
+            MerchantKind::Arcanist => vec![
+                (Wands, 60),
+                (Staves, 50),
+                (Consumables, 40),
+                (HeldItems, 25),
+                (WornItems, 25),
+                (Runes, 20),
+                (Grimoires, 20),
+                (Spellhearts, 10),
+            ],
+            MerchantKind::GeneralStore => vec![
+                (AdventuringGear, 80),
+                (TradeGoods, 30),
+                (Consumables, 20),
+                (AlchemicalItems, 15),
+                (Materials, 10),
+                (Weapons, 10),
+                (Armor, 5),
+                (Snares, 5),
+                (HeldItems, 5),
+            ],
+        }
+    }
+
+    /// Subcategory weights for this kind of merchant
+    /// Categories without an entry here are stocked from every subcategory
+    pub fn subcategory_weights(&self) -> &'static [SubcategoryWeight] {
+        use ItemCategory::*;
+        match self {
+            MerchantKind::Generic => &[],
+            MerchantKind::Blacksmith => &[
+                (Weapons, "Base Weapons", 60),
+                (Weapons, "Precious Material Weapons", 10),
+                (Weapons, "Basic Magic Weapons", 10),
+                (Weapons, "Specific Magic Weapons", 5),
+                (Armor, "Base Armor", 60),
+                (Armor, "Precious Material Armor", 10),
+                (Armor, "Basic Magic Armor", 10),
+                (Armor, "Specific Magic Armor", 5),
+                (Shields, "Base Shields", 60),
+                (Shields, "Precious Material Shields", 10),
+                (Shields, "Specific Shields", 5),
+                (Runes, "Fundamental Weapon Runes", 10),
+                (Runes, "Fundamental Armor Runes", 10),
+                (Runes, "Shield Rune", 5),
+            ],
+            MerchantKind::Alchemist => &[
+                (AlchemicalItems, "Alchemical Elixirs", 40),
+                (AlchemicalItems, "Alchemical Bombs", 25),
+                (AlchemicalItems, "Alchemical Tools", 20),
+                (AlchemicalItems, "Alchemical Food", 10),
+                (AlchemicalItems, "Alchemical Ammunition", 10),
+                (AlchemicalItems, "Alchemical Poisons", 10),
+                (AlchemicalItems, "Alchemical Other", 5),
+                (AlchemicalItems, "Alchemical Plants", 3),
+                (Consumables, "Potions", 30),
+                (Consumables, "Oils", 20),
+                (Consumables, "Other Consumables", 5),
+            ],
+            MerchantKind::Arcanist => &[
+                (Consumables, "Scrolls", 30),
+                (Consumables, "Talismans", 20),
+                (Consumables, "Spell Catalysts", 15),
+                (Consumables, "Potions", 10),
+                (Consumables, "Oils", 10),
+            ],
+            MerchantKind::GeneralStore => &[
+                (Consumables, "Potions", 20),
+                (Consumables, "Magical Ammunition", 10),
+                (Consumables, "Other Consumables", 10),
+                (AlchemicalItems, "Alchemical Food", 20),
+                (AlchemicalItems, "Alchemical Tools", 20),
+                (AlchemicalItems, "Alchemical Elixirs", 10),
+                (Weapons, "Base Weapons", 20),
+            ],
+        }
+    }
+
+    /// What this kind of merchant always keeps in stock
+    pub fn guaranteed_stock(&self) -> Vec<GuaranteedStock> {
+        let rations = GuaranteedStock {
+            min_quantity: Some(1),
+            budget_share: 1.0 / 24.0,
+            ..GuaranteedStock::named("Rations", 10)
+        };
+        match self {
+            MerchantKind::GeneralStore => vec![
+                GuaranteedStock {
+                    priority: 1,
+                    ..rations
+                },
+                GuaranteedStock {
+                    min_quantity: Some(5),
+                    budget_share: 0.01,
+                    ..GuaranteedStock::named("Torch", 10)
+                },
+                GuaranteedStock {
+                    min_quantity: Some(1),
+                    budget_share: 0.02,
+                    ..GuaranteedStock::named("Rope", 4)
+                },
+                GuaranteedStock {
+                    min_quantity: Some(1),
+                    quantity_per_level: 0.25,
+                    budget_share: 0.1,
+                    ..GuaranteedStock::matching(
+                        StockFilter {
+                            name_contains: Some("Healing Potion".to_string()),
+                            ..StockFilter::default()
+                        },
+                        3,
+                    )
+                },
+            ],
+            _ => vec![rations],
+        }
+    }
+
+    /// The generation profile for this kind of merchant
+    pub fn profile(&self) -> MerchantProfile {
+        let mut subcategory_weights: BTreeMap<ItemCategory, BTreeMap<String, u32>> =
+            BTreeMap::new();
+        for (category, subcategory, weight) in self.subcategory_weights() {
+            subcategory_weights
+                .entry(*category)
+                .or_default()
+                .insert(subcategory.to_string(), *weight);
+        }
+
+        MerchantProfile {
+            category_weights: self.category_weights().into_iter().collect(),
+            subcategory_weights,
+            default_subcategory_weight: DEFAULT_SUBCATEGORY_WEIGHT,
+            uncommon_chance: DEFAULT_UNCOMMON_CHANCE,
+            rare_chance: DEFAULT_RARE_CHANCE,
+            unique_chance: DEFAULT_UNIQUE_CHANCE,
+            min_uncommon: 0,
+            fill_target: DEFAULT_FILL_TARGET,
+            fill_tolerance: DEFAULT_FILL_TOLERANCE,
+            max_fill_attempts: DEFAULT_MAX_FILL_ATTEMPTS,
+            wealth_multiplier: 1.0,
+            min_level_offset: DEFAULT_MIN_LEVEL_OFFSET,
+            max_level_offset: DEFAULT_MAX_LEVEL_OFFSET,
+            level_falloff: DEFAULT_LEVEL_FALLOFF,
+            guaranteed_stock: self.guaranteed_stock(),
+            excluded_traits: vec![],
+            included_traits: vec![],
+            required_traits: vec![],
+            trait_weights: BTreeMap::new(),
+        }
+    }
+}
+
+impl AsRef<str> for MerchantKind {
+    fn as_ref(&self) -> &str {
+        match self {
+            MerchantKind::Generic => "generic",
+            MerchantKind::Blacksmith => "blacksmith",
+            MerchantKind::Alchemist => "alchemist",
+            MerchantKind::Arcanist => "arcanist",
+            MerchantKind::GeneralStore => "general-store",
+        }
+    }
+}
+
+impl std::fmt::Display for MerchantKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_ref())
+    }
+}
+
+impl std::str::FromStr for MerchantKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let normalized = s.to_lowercase().replace(['_', ' '], "-");
+        for kind in enum_iterator::all::<MerchantKind>() {
+            if kind.as_ref() == normalized || kind.as_ref().replace('-', "") == normalized {
+                return Ok(kind);
+            }
+        }
+        bail!(
+            "Unknown merchant kind: {} (expected one of: {})",
+            s,
+            enum_iterator::all::<MerchantKind>()
+                .map(|k| k.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::kind::MerchantKind;
+
+    #[test]
+    fn parse_kinds() {
+        let input = [
+            ("generic", MerchantKind::Generic),
+            ("Blacksmith", MerchantKind::Blacksmith),
+            ("alchemist", MerchantKind::Alchemist),
+            ("ARCANIST", MerchantKind::Arcanist),
+            ("general-store", MerchantKind::GeneralStore),
+            ("general_store", MerchantKind::GeneralStore),
+            ("generalstore", MerchantKind::GeneralStore),
+        ];
+
+        for (input, expected) in input {
+            assert_eq!(input.parse::<MerchantKind>().unwrap(), expected);
+        }
+        assert!("tavern".parse::<MerchantKind>().is_err());
+    }
+
+    #[test]
+    fn subcategories_belong_to_weighted_categories() {
+        for kind in enum_iterator::all::<MerchantKind>() {
+            let categories = kind.profile().categories();
+            for (category, _, _) in kind.subcategory_weights() {
+                assert!(categories.contains(category), "{kind}: {category:?}");
+            }
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use crate::item::format_cp;
+
+/// A record of items changing hands with a merchant
+#[derive(Debug, Clone, Eq, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Transaction {
+    pub kind: TransactionKind,
+    pub item: String,
+    pub quantity: u32,
+    /// The total price paid in cp
+    pub price_cp: i32,
+    /// Local time the transaction was recorded, in RFC 3339 format
+    pub timestamp: String,
+}
+
+/// Which way the items moved, from the point of view of the players
+#[derive(Debug, Clone, Copy, Eq, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum TransactionKind {
+    /// The players bought items from the merchant
+    Buy,
+    /// The players sold items to the merchant
+    Sell,
+}
+
+impl Transaction {
+    pub fn new<S: Into<String>>(
+        kind: TransactionKind,
+        item: S,
+        quantity: u32,
+        price_cp: i32,
+    ) -> Self {
+        Self {
+            kind,
+            item: item.into(),
+            quantity,
+            price_cp,
+            timestamp: chrono::offset::Local::now().to_rfc3339(),
+        }
+    }
+}
+
+impl std::fmt::Display for Transaction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let verb = match self.kind {
+            TransactionKind::Buy => "Bought",
+            TransactionKind::Sell => "Sold",
+        };
+        write!(
+            f,
+            "{} {} x{} for {}",
+            verb,
+            self.item,
+            self.quantity,
+            format_cp(self.price_cp)
+        )
+    }
+}
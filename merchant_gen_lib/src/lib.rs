@@ -1,3 +1,12 @@
+pub mod budget;
+pub mod catalog;
 pub mod database;
+pub mod export;
+pub mod inventory;
 pub mod item;
+pub mod kind;
+pub mod ledger;
 pub mod merchant;
+pub mod profile;
+pub mod settlement;
+pub mod wealth;
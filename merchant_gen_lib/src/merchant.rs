@@ -1,275 +1,1128 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, ops::RangeInclusive, path::Path};
 
 use crate::{
-    database,
-    item::{Item, ItemCategory, Price, Rarity},
+    budget::{Budget, FillStop, FillSummary},
+    catalog::ItemCatalog,
+    export,
+    inventory::Inventory,
+    item::{format_cp, Item, ItemCategory, Rarity},
+    kind::MerchantKind,
+    ledger::{Transaction, TransactionKind},
+    profile::{GuaranteedStock, MerchantProfile},
+    wealth::WealthTable,
 };
-use anyhow::Result;
-use enum_derived::Rand;
-use rand::{seq::SliceRandom, Rng, SeedableRng};
+use anyhow::{bail, Context, Result};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
 use sqlx::{Pool, Sqlite};
 
-const UNCOMMON_CHANCE: f32 = 0.005;
-const RARE_CHANCE: f32 = 0.001;
 const MERCHANT_WEALTH_DIVISOR: i32 = 3;
+/// The most gp a merchant can have, as wealth is kept in cp
+pub const MAX_GP: i32 = i32::MAX / 100;
+/// Merchants buy items for half their price
+const SELL_PRICE_DIVISOR: i32 = 2;
+/// Chance for each item in stock to be sold to someone else on any given day
+const DAILY_TURNOVER: f64 = 0.05;
 
-#[derive(
-    Debug,
-    Clone,
-    Eq,
-    PartialEq,
-    Ord,
-    PartialOrd,
-    serde::Serialize,
-    serde::Deserialize,
-    sqlx::FromRow,
-)]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, sqlx::FromRow)]
 pub struct Merchant {
     /// The merchant's wealth in cp
     wealth: i32,
+    /// The wealth in cp the merchant drifts back to as they restock, before
+    /// their profile's multiplier
+    /// Merchants saved before this was recorded use the wealth for their level
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    base_wealth: Option<i32>,
     level: i32,
-    inventory: Vec<Item>,
+    #[serde(default)]
+    kind: MerchantKind,
+    /// The seed the inventory was generated from, if it is known
+    #[serde(default)]
+    seed: Option<u64>,
+    /// A custom profile that overrides the one for the merchant's kind
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    profile: Option<MerchantProfile>,
+    inventory: Inventory,
+    /// Every purchase and sale made with the merchant, oldest first
+    #[serde(default)]
+    transactions: Vec<Transaction>,
+}
+
+/// What changed when a merchant restocked
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct RestockSummary {
+    /// Items sold to other customers
+    pub sold: usize,
+    /// Items added to the shelves
+    pub added: usize,
+    /// How filling the shelves back up went
+    pub fill: FillSummary,
 }
 
 impl Merchant {
     pub fn new(cp: i32, level: i32) -> Self {
         Self {
             wealth: cp,
+            base_wealth: Some(cp),
             level,
-            inventory: vec![],
+            kind: MerchantKind::default(),
+            seed: None,
+            profile: None,
+            inventory: Inventory::new(),
+            transactions: vec![],
         }
     }
 
+    pub fn with_kind(mut self, kind: MerchantKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    pub fn kind(&self) -> MerchantKind {
+        self.kind
+    }
+
+    /// Use a custom profile instead of the one for the merchant's kind
+    /// The profile's wealth multiplier is applied to the merchant's wealth
+    pub fn with_profile(mut self, profile: MerchantProfile) -> Self {
+        self.wealth = (self.wealth as f32 * profile.wealth_multiplier) as i32;
+        self.profile = Some(profile);
+        self
+    }
+
+    /// The profile used to generate the merchant's inventory
+    pub fn profile(&self) -> MerchantProfile {
+        self.profile.clone().unwrap_or_else(|| self.kind.profile())
+    }
+
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    /// The merchant's wealth in cp
+    pub fn wealth(&self) -> i32 {
+        self.wealth
+    }
+
+    pub fn level(&self) -> i32 {
+        self.level
+    }
+
+    pub fn inventory(&self) -> &Inventory {
+        &self.inventory
+    }
+
+    /// A merchant with `gp` wealth, capped at the most that fits in cp
     pub fn from_gp(gp: i32, level: i32) -> Self {
-        Self::new(gp * 100, level)
+        Self::new(gp.saturating_mul(100), level)
     }
 
+    /// A merchant with the usual wealth for their level, see `WealthTable`
     pub fn by_level(level: i32) -> Self {
-        let gp = match level {
-            // numbers taken from Treasure By Level table for players
-            1 => 175 / MERCHANT_WEALTH_DIVISOR,
-            2 => 300 / MERCHANT_WEALTH_DIVISOR,
-            3 => 500 / MERCHANT_WEALTH_DIVISOR,
-            4 => 850 / MERCHANT_WEALTH_DIVISOR,
-            5 => 1350 / MERCHANT_WEALTH_DIVISOR,
-            6 => 2000 / MERCHANT_WEALTH_DIVISOR,
-            7 => 2900 / MERCHANT_WEALTH_DIVISOR,
-            8 => 4000 / MERCHANT_WEALTH_DIVISOR,
-            9 => 5700 / MERCHANT_WEALTH_DIVISOR,
-            10 => 8000 / MERCHANT_WEALTH_DIVISOR,
-            11 => 11500 / MERCHANT_WEALTH_DIVISOR,
-            12 => 16500 / MERCHANT_WEALTH_DIVISOR,
-            13 => 25000 / MERCHANT_WEALTH_DIVISOR,
-            14 => 36500 / MERCHANT_WEALTH_DIVISOR,
-            15 => 54500 / MERCHANT_WEALTH_DIVISOR,
-            16 => 82500 / MERCHANT_WEALTH_DIVISOR,
-            17 => 128000 / MERCHANT_WEALTH_DIVISOR,
-            18 => 208000 / MERCHANT_WEALTH_DIVISOR,
-            19 => 355000 / MERCHANT_WEALTH_DIVISOR,
-            20 => 490000 / MERCHANT_WEALTH_DIVISOR,
-            _ => unreachable!(),
-        };
-        Self::from_gp(gp, level)
+        Self::by_level_in(level, WealthTable::bundled())
+    }
+
+    /// A merchant with the wealth for their level from a custom table
+    /// Merchants keep a fraction of the party treasure for their level
+    pub fn by_level_in(level: i32, table: &WealthTable) -> Self {
+        let gp = table.gp(level) / MERCHANT_WEALTH_DIVISOR as i64;
+        Self::from_gp(gp.clamp(0, MAX_GP as i64) as i32, level)
     }
 
+    /// Multiply the merchant's wealth, e.g. for a poor village or a rich
+    /// capital
+    pub fn with_wealth_multiplier(mut self, multiplier: f32) -> Self {
+        self.wealth = (self.wealth as f32 * multiplier) as i32;
+        self.base_wealth = self.base_wealth.map(|w| (w as f32 * multiplier) as i32);
+        self
+    }
+
+    /// The wealth in cp the merchant drifts back to as they restock, before
+    /// their profile's multiplier
+    fn base_wealth(&self) -> i32 {
+        self.base_wealth
+            .unwrap_or_else(|| Merchant::by_level(self.level).wealth)
+    }
+
+    /// Only stock items from `levels`, instead of the window in the profile
+    pub fn with_item_levels(mut self, levels: RangeInclusive<i32>) -> Self {
+        let mut profile = self.profile();
+        profile.min_level_offset = levels.start() - self.level;
+        profile.max_level_offset = levels.end() - self.level;
+        self.profile = Some(profile);
+        self
+    }
+
+    /// Only stock items with at least one of `traits`, on top of the profile's
+    /// trait rules
+    pub fn with_included_traits<I, S>(mut self, traits: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let mut profile = self.profile();
+        profile
+            .included_traits
+            .extend(traits.into_iter().map(Into::into));
+        self.profile = Some(profile);
+        self
+    }
+
+    /// Only stock items with every one of `traits`, on top of the profile's
+    /// trait rules
+    pub fn with_required_traits<I, S>(mut self, traits: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let mut profile = self.profile();
+        profile
+            .required_traits
+            .extend(traits.into_iter().map(Into::into));
+        self.profile = Some(profile);
+        self
+    }
+
+    /// Never stock items with any of `traits`, on top of the profile's trait
+    /// rules
+    pub fn with_excluded_traits<I, S>(mut self, traits: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let mut profile = self.profile();
+        profile
+            .excluded_traits
+            .extend(traits.into_iter().map(Into::into));
+        self.profile = Some(profile);
+        self
+    }
+
+    /// Multiply the chance to stock items with `trait_name` by `weight`
+    pub fn with_trait_weight<S: Into<String>>(mut self, trait_name: S, weight: f32) -> Self {
+        let mut profile = self.profile();
+        profile.trait_weights.insert(trait_name.into(), weight);
+        self.profile = Some(profile);
+        self
+    }
+
+    #[deprecated(note = "use `Merchant::load`, which returns an error instead of panicking")]
     pub fn read_from_file<S: AsRef<str>>(filename: S) -> Self {
-        let ron = std::fs::read_to_string(filename.as_ref()).unwrap();
-        ron::from_str(&ron).unwrap()
+        Self::load(filename.as_ref()).unwrap()
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let ron = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        ron::from_str(&ron).with_context(|| format!("Failed to parse {}", path.display()))
     }
 
     pub fn save(&self) -> Result<()> {
-        let ron = ron::to_string(self)?;
         let filename = format!(
             "{}.ron",
             chrono::offset::Local::now().format("%Y-%m-%d_%I:%M %p")
         );
-        std::fs::write(filename, ron)?;
+        self.save_to(filename)
+    }
+
+    pub fn save_to<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let ron = ron::to_string(self)?;
+        std::fs::write(path, ron)?;
         Ok(())
     }
 
+    pub fn transactions(&self) -> &[Transaction] {
+        &self.transactions
+    }
+
+    /// The players buy `quantity` of the item called `name` at full price
+    pub fn buy(&mut self, name: &str, quantity: u32) -> Result<Transaction> {
+        if quantity == 0 {
+            bail!("Can't buy none of {}", name);
+        }
+        let stack = self
+            .inventory
+            .get(name)
+            .with_context(|| format!("{} is not in stock", name))?;
+        let price = stack
+            .item
+            .price
+            .as_ref()
+            .with_context(|| format!("{} has no price", stack.item.name))?
+            .cost_cp(quantity);
+
+        let item = self.inventory.remove(name, quantity)?;
+        self.wealth += price;
+
+        let transaction = Transaction::new(TransactionKind::Buy, item.name, quantity, price);
+        self.transactions.push(transaction.clone());
+        Ok(transaction)
+    }
+
+    /// The players sell `quantity` of `item` to the merchant
+    /// The merchant pays half the item's price unless `unit_price` (in cp) is given
+    pub fn sell(
+        &mut self,
+        item: Item,
+        quantity: u32,
+        unit_price: Option<i32>,
+    ) -> Result<Transaction> {
+        if quantity == 0 {
+            bail!("Can't sell none of {}", item.name);
+        }
+        if unit_price.is_some_and(|price| price < 0) {
+            bail!("The price of {} can't be negative", item.name);
+        }
+        if quantity > self.inventory.room_for(&item) {
+            bail!(
+                "The merchant has no room for {} more {}",
+                quantity,
+                item.name
+            );
+        }
+        let price = match unit_price {
+            Some(price) => i32::try_from(quantity)
+                .ok()
+                .and_then(|quantity| price.checked_mul(quantity))
+                .with_context(|| format!("{} {} cost too much", quantity, item.name))?,
+            None => {
+                item.price
+                    .as_ref()
+                    .with_context(|| format!("{} has no price", item.name))?
+                    .cost_cp(quantity)
+                    / SELL_PRICE_DIVISOR
+            }
+        };
+        if price > self.wealth {
+            bail!(
+                "The merchant can't afford {}, they only have {}",
+                format_cp(price),
+                format_cp(self.wealth)
+            );
+        }
+
+        self.wealth -= price;
+        let transaction = Transaction::new(TransactionKind::Sell, &item.name, quantity, price);
+        self.inventory.add(item, quantity);
+        self.transactions.push(transaction.clone());
+        Ok(transaction)
+    }
+
     pub fn markdown(&self) -> String {
         let s = self.to_string();
         // TODO: remove trailing ##
         let s = s.replace("----------", "##");
-        let s = s.replace("\n", "  \n");
-        return s;
+        s.replace("\n", "  \n")
+    }
+
+    /// The merchant in the schema documented in `export::json`
+    pub fn json(&self) -> Result<String> {
+        export::json::to_string(self)
+    }
+
+    /// The merchant as a Foundry VTT loot actor, see `export::foundry`
+    pub fn foundry(&self) -> Result<String> {
+        export::foundry::to_string(self, self.name())
+    }
+
+    /// "Level 5 blacksmith Merchant"
+    pub fn name(&self) -> String {
+        format!("Level {} {} Merchant", self.level, self.kind)
     }
 
     pub fn len(&self) -> usize {
         self.inventory.len()
     }
 
-    pub async fn generate_inventory(&mut self, pool: &Pool<Sqlite>) -> Result<()> {
-        let mut rations_allowance = self.wealth / 24;
+    pub fn is_empty(&self) -> bool {
+        self.inventory.is_empty()
+    }
+
+    /// Generate an inventory from a random seed
+    /// The seed is recorded so the inventory can be regenerated later
+    pub async fn generate_inventory(&mut self, pool: &Pool<Sqlite>) -> Result<FillSummary> {
+        self.generate_inventory_with_seed(pool, rand::random())
+            .await
+    }
 
-        let rations = database::get_rations(pool).await;
-        let rations_price = rations.price.clone().unwrap().as_cp();
+    /// Generate an inventory from `seed`
+    /// The same seed, level and kind always produce the same inventory
+    pub async fn generate_inventory_with_seed(
+        &mut self,
+        pool: &Pool<Sqlite>,
+        seed: u64,
+    ) -> Result<FillSummary> {
+        let catalog = ItemCatalog::load(pool).await?;
+        Ok(self.generate_inventory_from(&catalog, seed))
+    }
 
-        let mut count = 0;
-        while rations_allowance > 0 && count < 10 {
-            self.inventory.push(rations.clone());
-            rations_allowance -= rations_price;
-            count += 1;
-        }
+    /// Generate an inventory from `seed` using items from `catalog`
+    /// Loading the catalog once and sharing it is much faster when generating
+    /// many merchants
+    pub fn generate_inventory_from(&mut self, catalog: &ItemCatalog, seed: u64) -> FillSummary {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let summary = self.generate_inventory_with_rng(catalog, &mut rng);
+        self.seed = Some(seed);
+        summary
+    }
 
-        self.add_all_to_inv(pool, self.wealth).await?;
-        self.inventory
-            .sort_unstable_by(|a, b| a.item_category.cmp(&b.item_category));
+    /// Generate an inventory using a caller-provided rng for every random decision
+    /// No seed is recorded since it can't be recovered from the rng
+    pub fn generate_inventory_with_rng<R: Rng + ?Sized>(
+        &mut self,
+        catalog: &ItemCatalog,
+        rng: &mut R,
+    ) -> FillSummary {
+        self.seed = None;
+        let profile = self.profile();
 
-        Ok(())
+        self.add_guaranteed_to_inv(catalog, &profile, rng);
+        let summary = self.add_all_to_inv(catalog, &profile, 1.0, rng);
+        self.inventory.sort();
+
+        summary
     }
 
-    pub fn get_wealth_in_inv(&self) -> i32 {
-        let mut sum = 0;
-        for i in self.inventory.iter() {
-            sum += i.price.as_ref().unwrap().as_cp();
-        }
+    /// Simulate `days` of in-game time passing since the players last visited
+    pub async fn restock(&mut self, pool: &Pool<Sqlite>, days: u32) -> Result<RestockSummary> {
+        let catalog = ItemCatalog::load(pool).await?;
+        let mut rng = StdRng::from_entropy();
+        self.restock_with_rng(&catalog, days, &mut rng)
+    }
 
-        sum
+    /// Simulate `days` of in-game time passing, rolling everything from `seed`
+    /// The same merchant, days and seed always restock the same way
+    pub async fn restock_with_seed(
+        &mut self,
+        pool: &Pool<Sqlite>,
+        days: u32,
+        seed: u64,
+    ) -> Result<RestockSummary> {
+        let catalog = ItemCatalog::load(pool).await?;
+        let mut rng = StdRng::seed_from_u64(seed);
+        self.restock_with_rng(&catalog, days, &mut rng)
     }
 
-    async fn add_all_to_inv(&mut self, pool: &Pool<Sqlite>, mut allowance: i32) -> Result<()> {
-        let mut rng = rand::rngs::StdRng::seed_from_u64(
-            std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_secs(),
-        );
-        let minimums = database::get_min_for_each_category(pool, self.level).await?;
+    /// Simulate `days` of in-game time passing, using a caller-provided rng
+    ///
+    /// Other customers buy some of the stock, the merchant's wealth drifts back
+    /// towards what's normal for their level, and the shelves are filled back up
+    /// to the merchant's wealth using their profile. The longer the wait, the
+    /// more of the stock turns over.
+    /// If the stock changes it no longer matches the seed it was generated
+    /// from, so the seed is cleared.
+    pub fn restock_with_rng<R: Rng + ?Sized>(
+        &mut self,
+        catalog: &ItemCatalog,
+        days: u32,
+        rng: &mut R,
+    ) -> Result<RestockSummary> {
+        let turnover = 1.0 - (1.0 - DAILY_TURNOVER).powf(days as f64);
+        let profile = self.profile();
+        let before = self.len();
 
-        #[allow(unused_assignments)]
-        let mut minimum = 0;
+        let mut sold = Inventory::new();
+        for stack in self.inventory.iter() {
+            let count = (0..stack.quantity)
+                .filter(|_| rng.gen_bool(turnover))
+                .count();
+            sold.add(stack.item.clone(), count as u32);
+        }
+        for stack in sold.iter() {
+            self.inventory.remove_item(&stack.item, stack.quantity)?;
+        }
 
-        while allowance > 0 {
-            let category = ItemCategory::rand();
-            let temp = minimums.get(&category);
+        let base = (self.base_wealth() as f32 * profile.wealth_multiplier) as i32;
+        self.wealth += ((base - self.wealth) as f64 * turnover) as i32;
 
-            if temp.is_none() {
-                continue;
-            }
-            minimum = *temp.unwrap();
+        // like sales, restocking grows with the wait, so no time passing
+        // restocks nothing
+        if days > 0 {
+            self.add_guaranteed_to_inv(catalog, &profile, rng);
+        }
+        let fill = self.add_all_to_inv(catalog, &profile, turnover, rng);
+        self.inventory.sort();
 
-            let items =
-                database::get_category(pool, category, Rarity::Common, self.level, true).await?;
-            let uncommon =
-                database::get_category(pool, category, Rarity::Uncommon, self.level, true).await?;
-            let rare =
-                database::get_category(pool, category, Rarity::Rare, self.level, true).await?;
+        let summary = RestockSummary {
+            sold: sold.len(),
+            added: self.len() + sold.len() - before,
+            fill,
+        };
+        if summary.sold > 0 || summary.added > 0 {
+            self.seed = None;
+        }
+
+        Ok(summary)
+    }
+
+    /// Top up the profile's guaranteed stock, highest priority first
+    ///
+    /// Each rule rolls how many to keep, stocks at least its minimum as long
+    /// as the merchant can afford it, and stocks the rest out of its share of
+    /// the merchant's wealth.
+    fn add_guaranteed_to_inv<R: Rng + ?Sized>(
+        &mut self,
+        catalog: &ItemCatalog,
+        profile: &MerchantProfile,
+        rng: &mut R,
+    ) {
+        let mut rules = profile.guaranteed_stock.iter().collect::<Vec<_>>();
+        rules.sort_by_key(|rule| std::cmp::Reverse(rule.priority));
+        let stock_target = (self.wealth as f64 * profile.fill_target.clamp(0.0, 1.0) as f64) as i32;
 
-            if allowance < minimum {
+        for rule in rules {
+            let quantity = rule.quantity(self.level);
+            let wanted = rng.gen_range(quantity.clone());
+            let held = self
+                .inventory
+                .iter()
+                .filter(|s| rule.covers(&s.item, self.level))
+                .collect::<Vec<_>>();
+            let mut count = held.iter().map(|s| s.quantity).sum::<u32>();
+            if count >= wanted {
                 continue;
             }
+            let mut allowance = (self.wealth as f32 * rule.budget_share) as i32
+                - held.iter().filter_map(|s| s.value_cp()).sum::<i32>();
+            let mut remaining = stock_target - self.inventory.value_cp();
 
-            let mut choice = items.choose(&mut rng).unwrap();
-            let mut price = choice.price.as_ref().unwrap().as_cp();
-            while price > allowance {
-                choice = items.choose(&mut rng).unwrap();
-                price = choice.price.as_ref().unwrap().as_cp();
-            }
+            let Some(item) = guaranteed_item(catalog, profile, rule, self.level, remaining, rng)
+            else {
+                continue;
+            };
+            let price = item.price.as_ref().unwrap();
 
-            let upgrade_roll = rng.gen_range(0.0..1.0);
-            if upgrade_roll <= UNCOMMON_CHANCE {
-                let maybe_choice = uncommon.choose(&mut rng);
-                if maybe_choice.is_some() {
-                    choice = maybe_choice.unwrap();
-                }
-            } else if upgrade_roll <= RARE_CHANCE {
-                let maybe_choice = rare.choose(&mut rng);
-                if maybe_choice.is_some() {
-                    choice = maybe_choice.unwrap();
+            // items sold in batches are stocked a whole batch at a time
+            while count < wanted && price.as_cp() <= remaining {
+                if count >= *quantity.start() && price.as_cp() > allowance {
+                    break;
                 }
+                self.inventory.add(item.clone(), price.batch());
+                allowance -= price.as_cp();
+                remaining -= price.as_cp();
+                count += price.batch();
             }
-
-            self.inventory.push(choice.clone());
-            allowance -= price;
         }
+    }
 
-        Ok(())
+    pub fn get_wealth_in_inv(&self) -> i32 {
+        self.inventory.value_cp()
     }
 
-    #[allow(dead_code)]
-    async fn add_category_to_inv<F: Fn(i32, i32) -> bool>(
+    /// Fill the shelves using the profile
+    ///
+    /// The merchant aims to hold the profile's `fill_target` share of their
+    /// wealth in stock, counting what's already on the shelves, and stops once
+    /// they're within `fill_tolerance` of it, once nothing left is affordable,
+    /// or after `max_fill_attempts` picks, so filling always finishes.
+    /// Only `share` of the gap between the stock and that target is filled, so
+    /// a restock after a short wait only puts back part of what's missing.
+    fn add_all_to_inv<R: Rng + ?Sized>(
         &mut self,
-        pool: &Pool<Sqlite>,
-        category: ItemCategory,
-        subcategory: Option<&str>,
-        mut allowance: i32,
-        predicate: F,
-    ) -> Result<()> {
-        let mut rng = rand::rngs::StdRng::seed_from_u64(
-            std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_secs(),
-        );
+        catalog: &ItemCatalog,
+        profile: &MerchantProfile,
+        share: f64,
+        rng: &mut R,
+    ) -> FillSummary {
+        let mut levels = profile.level_window(self.level);
+        // there are hardly any common items past level 20, so the highest
+        // level merchants reach down to the best there is
+        if let Some(max) = catalog.max_level(Rarity::Common) {
+            levels = (*levels.start()).min(max)..=*levels.end();
+        }
+        let share = share.clamp(0.0, 1.0);
+        let stock_target = self.wealth as f64 * profile.fill_target.clamp(0.0, 1.0) as f64;
+        // the tolerance is a share of the whole stock, so topping up a
+        // nearly full shop doesn't chase the last few coins
+        let tolerance = (stock_target * profile.fill_tolerance.max(0.0) as f64 * share) as i32;
+        let gap = stock_target - self.inventory.value_cp() as f64;
+        let mut budget = Budget::new((gap * share) as i32);
 
-        let mut items =
-            database::get_category(pool, category, Rarity::Common, self.level, true).await?;
-        if let Some(subcategory) = subcategory {
-            items.retain(|i| i.item_subcategory == subcategory);
+        // everything the profile allows, filtered once up front
+        let mut stock: HashMap<(ItemCategory, Rarity), Vec<&Item>> = HashMap::new();
+        for category in profile.categories() {
+            for rarity in enum_iterator::all::<Rarity>() {
+                let items = catalog
+                    .items(category, rarity, levels.clone())
+                    .iter()
+                    .filter(|i| {
+                        profile.allows(i)
+                            && profile.subcategory_weight(category, &i.item_subcategory) > 0
+                    })
+                    .collect();
+                stock.insert((category, rarity), items);
+            }
         }
-        let items = items; // immutable rebind
+        let items_in = |category: ItemCategory, rarity: Rarity| {
+            stock
+                .get(&(category, rarity))
+                .map(|items| items.as_slice())
+                .unwrap_or_default()
+        };
+        // the cheapest common item in each category, so categories with
+        // nothing affordable left are never picked
+        let minimums = profile
+            .categories()
+            .into_iter()
+            .filter_map(|category| {
+                let min = items_in(category, Rarity::Common)
+                    .iter()
+                    .filter_map(|i| i.price.as_ref().map(|p| p.as_cp()))
+                    .min()?;
+                Some((category, min))
+            })
+            .collect::<HashMap<_, _>>();
 
-        let uncommon =
-            database::get_category(pool, category, Rarity::Uncommon, self.level, true).await?;
-        let rare = database::get_category(pool, category, Rarity::Rare, self.level, true).await?;
+        let mut added = 0;
 
-        let mut count = 0;
+        // stock the profile's minimum of uncommon items first, so they aren't
+        // crowded out by cheaper common items
+        let mut uncommon_count = self
+            .inventory
+            .iter()
+            .filter(|s| s.item.rarity != Rarity::Common)
+            .map(|s| s.quantity)
+            .sum::<u32>();
+        let uncommon = profile
+            .categories()
+            .into_iter()
+            .flat_map(|category| items_in(category, Rarity::Uncommon).iter().copied())
+            .collect::<Vec<_>>();
+        while uncommon_count < profile.min_uncommon {
+            let Some(choice) = pick_affordable(
+                uncommon.iter().copied(),
+                budget.remaining(),
+                self.level,
+                profile,
+                rng,
+            ) else {
+                break;
+            };
+            let price = choice.price.as_ref().unwrap();
+            self.inventory.add(choice.clone(), price.batch());
+            budget.spend(price.as_cp());
+            uncommon_count += price.batch();
+            added += price.batch();
+        }
 
-        while predicate(allowance, count) {
-            let mut choice = items.choose(&mut rng).unwrap();
-            let mut price = choice.price.as_ref().unwrap().as_cp();
-            while price > allowance {
-                choice = items.choose(&mut rng).unwrap();
-                price = choice.price.as_ref().unwrap().as_cp();
+        let mut attempts = 0;
+        let stop = loop {
+            if budget.remaining() <= tolerance {
+                break FillStop::TargetReached;
+            }
+            if attempts >= profile.max_fill_attempts {
+                break FillStop::AttemptsExhausted;
             }
+            let Some(category) = profile.pick_category_where(rng, |category| {
+                minimums
+                    .get(&category)
+                    .is_some_and(|min| budget.can_afford(*min))
+            }) else {
+                break FillStop::NothingAffordable;
+            };
+            attempts += 1;
 
-            let upgrade_roll = rng.gen_range(0.0..1.0);
-            if upgrade_roll <= UNCOMMON_CHANCE {
-                choice = uncommon.choose(&mut rng).unwrap();
-            } else if upgrade_roll <= RARE_CHANCE {
-                choice = rare.choose(&mut rng).unwrap();
+            let rarity = profile.pick_rarity(rng);
+            let mut items = items_in(category, Rarity::Common).to_vec();
+            let mut upgrades = match rarity {
+                Rarity::Common => vec![],
+                _ => items_in(category, rarity).to_vec(),
+            };
+
+            // only subcategories with something affordable are picked
+            let affordable = items
+                .iter()
+                .copied()
+                .filter(|i| budget.can_afford(i.price.as_ref().unwrap().as_cp()))
+                .map(|i| i.item_subcategory.as_str());
+            if let Some(subcategory) = profile.pick_subcategory(category, affordable, rng) {
+                items.retain(|i| i.item_subcategory == subcategory);
+                upgrades.retain(|i| i.item_subcategory == subcategory);
             }
 
-            self.inventory.push(choice.clone());
-            allowance -= price;
-            count += 1;
+            // an upgrade only happens if the merchant can afford one, otherwise
+            // they stock a common item instead
+            let remaining = budget.remaining();
+            let Some(choice) = pick_affordable(upgrades, remaining, self.level, profile, rng)
+                .or_else(|| pick_affordable(items, remaining, self.level, profile, rng))
+            else {
+                continue;
+            };
+            let price = choice.price.as_ref().unwrap();
+            self.inventory.add(choice.clone(), price.batch());
+            budget.spend(price.as_cp());
+            added += price.batch();
+        };
+
+        FillSummary {
+            budget,
+            items: added,
+            attempts,
+            stop,
         }
+    }
+}
 
-        Ok(())
+/// The item a guaranteed stock rule stocks for a merchant of `level`
+/// Rules with a filter pick an affordable match, favoring items near the
+/// merchant's level
+fn guaranteed_item<'a, R: Rng + ?Sized>(
+    catalog: &'a ItemCatalog,
+    profile: &MerchantProfile,
+    rule: &GuaranteedStock,
+    level: i32,
+    allowance: i32,
+    rng: &mut R,
+) -> Option<&'a Item> {
+    let Some(filter) = &rule.filter else {
+        // the catalog only has items with a price
+        let Some(item) = catalog.get(&rule.name) else {
+            tracing::warn!("Guaranteed item not found or has no price: {}", rule.name);
+            return None;
+        };
+        // a named item is stocked whatever traits the profile asks for, unless
+        // it has one the profile bans
+        return (!profile.excludes(item)).then_some(item);
+    };
+
+    let levels = i32::MIN..=level + filter.max_level_offset;
+    let matching = enum_iterator::all::<ItemCategory>()
+        .filter(|c| filter.category.is_none_or(|f| f == *c))
+        .flat_map(|c| catalog.items(c, Rarity::Common, levels.clone()))
+        .filter(|i| filter.matches(i, level) && profile.allows(i));
+    let item = pick_affordable(matching, allowance, level, profile, rng);
+    if item.is_none() {
+        tracing::debug!("Nothing affordable matches guaranteed stock {:?}", filter);
     }
+    item
+}
+
+/// Pick an item the merchant can afford from `items`, favoring items near the
+/// merchant's level
+/// Returns `None` if nothing is affordable
+fn pick_affordable<'a, I, R>(
+    items: I,
+    allowance: i32,
+    level: i32,
+    profile: &MerchantProfile,
+    rng: &mut R,
+) -> Option<&'a Item>
+where
+    I: IntoIterator<Item = &'a Item>,
+    R: Rng + ?Sized,
+{
+    let affordable = items
+        .into_iter()
+        .filter(|i| i.price.as_ref().is_some_and(|p| p.as_cp() <= allowance))
+        .collect::<Vec<_>>();
+    affordable
+        .choose_weighted(rng, |i| {
+            profile.level_weight(level, i.level) * profile.trait_weight(i)
+        })
+        .ok()
+        .copied()
 }
 
 impl std::fmt::Display for Merchant {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let mut categories: HashMap<&str, HashMap<&str, (i32, &Price)>> = HashMap::new();
-        for item in self.inventory.iter() {
-            if categories.contains_key(item.item_category.as_str()) {
-                // outer has key
-                let inner = categories.get_mut(item.item_category.as_str()).unwrap();
-
-                if inner.contains_key(item.name.as_str()) {
-                    // inner has key
-                    inner.get_mut(item.name.as_str()).unwrap().0 += 1;
-                } else {
-                    // inner doesn't have key
-                    inner.insert(item.name.as_str(), (1, item.price.as_ref().unwrap()));
-                }
-            } else {
-                // outer doesn't have key
-                let mut new_inner = HashMap::new();
-                new_inner.insert(item.name.as_str(), (1, item.price.as_ref().unwrap()));
-                categories.insert(item.item_category.as_str(), new_inner);
+        let mut category = None;
+        for stack in self.inventory.sorted() {
+            if category != Some(&stack.item.item_category) {
+                category = Some(&stack.item.item_category);
+                writeln!(f, "\n---------- {} ----------", stack.item.item_category)?;
+            }
+            let quantity = stack.quantity_label();
+            match &stack.item.price {
+                Some(price) => writeln!(f, "{} {} - {}", stack.item.name, quantity, price)?,
+                None => writeln!(f, "{} {}", stack.item.name, quantity)?,
             }
         }
 
-        for (key, items) in categories {
-            writeln!(f, "\n---------- {} ----------", key)?;
-            for (name, (count, price)) in items {
-                writeln!(f, "{} x{} - {}", name, count, price)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        budget::FillStop,
+        catalog::ItemCatalog,
+        database::{self, test_pool},
+        item::{ItemCategory, Rarity},
+        kind::MerchantKind,
+        ledger::TransactionKind,
+        merchant::Merchant,
+        profile::{GuaranteedStock, MerchantProfile},
+    };
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[tokio::test]
+    async fn same_seed_same_inventory() {
+        let pool = test_pool().await;
+
+        for kind in [MerchantKind::Generic, MerchantKind::Alchemist] {
+            let mut first = Merchant::by_level(5).with_kind(kind);
+            first.generate_inventory_with_seed(&pool, 42).await.unwrap();
+            let mut second = Merchant::by_level(5).with_kind(kind);
+            second
+                .generate_inventory_with_seed(&pool, 42)
+                .await
+                .unwrap();
+
+            assert!(!first.is_empty());
+            assert_eq!(first, second);
+            assert_eq!(first.seed(), Some(42));
+
+            let ron = ron::to_string(&first).unwrap();
+            let loaded: Merchant = ron::from_str(&ron).unwrap();
+            assert_eq!(loaded, first);
+        }
+    }
+
+    #[tokio::test]
+    async fn stock_stays_in_level_window() {
+        let pool = test_pool().await;
+        let mut merchant = Merchant::by_level(12).with_kind(MerchantKind::Arcanist);
+        merchant
+            .generate_inventory_with_seed(&pool, 5)
+            .await
+            .unwrap();
+        let window = merchant.profile().level_window(12);
+        assert_eq!(window, 8..=13);
+        let levels = merchant
+            .inventory()
+            .iter()
+            .filter(|s| s.item.name != "Rations")
+            .map(|s| s.item.level)
+            .collect::<Vec<_>>();
+        assert!(levels.iter().all(|l| window.contains(l)));
+        // items near the merchant's level are favored
+        let profile = merchant.profile();
+        assert_eq!(profile.level_weight(12, 12), 1.0);
+        assert!(profile.level_weight(12, 11) > profile.level_weight(12, 8));
+        assert_eq!(profile.level_weight(12, 13), profile.level_weight(12, 11));
+
+        let mut merchant = Merchant::by_level(12).with_item_levels(3..=5);
+        merchant
+            .generate_inventory_with_seed(&pool, 5)
+            .await
+            .unwrap();
+        assert!(merchant
+            .inventory()
+            .iter()
+            .filter(|s| s.item.name != "Rations")
+            .all(|s| (3..=5).contains(&s.item.level)));
+
+        // past the highest level of common item, the window reaches down to it
+        let mut merchant = Merchant::by_level(30);
+        let fill = merchant
+            .generate_inventory_with_seed(&pool, 5)
+            .await
+            .unwrap();
+        assert!(fill.is_complete(), "{:?}", fill);
+        assert!(merchant.len() > 10);
+    }
+
+    #[tokio::test]
+    async fn upgrades_stay_in_budget() {
+        let pool = test_pool().await;
+        let mut profile = MerchantKind::Generic.profile();
+        profile.uncommon_chance = 0.5;
+        profile.rare_chance = 0.5;
+        profile.guaranteed_stock.clear();
+        let mut merchant = Merchant::by_level(3).with_profile(profile.clone());
+        merchant
+            .generate_inventory_with_seed(&pool, 11)
+            .await
+            .unwrap();
+        assert!(merchant.inventory().value_cp() <= merchant.wealth());
+        assert!(merchant
+            .inventory()
+            .iter()
+            .any(|s| s.item.rarity == Rarity::Rare));
+
+        profile.uncommon_chance = 0.0;
+        profile.rare_chance = 0.0;
+        profile.min_uncommon = 3;
+        let mut merchant = Merchant::by_level(8).with_profile(profile);
+        merchant
+            .generate_inventory_with_seed(&pool, 11)
+            .await
+            .unwrap();
+        let uncommon = merchant
+            .inventory()
+            .iter()
+            .filter(|s| s.item.rarity == Rarity::Uncommon)
+            .map(|s| s.quantity)
+            .sum::<u32>();
+        assert!(uncommon >= 3);
+    }
+
+    #[tokio::test]
+    async fn fill_always_finishes() {
+        let pool = test_pool().await;
+        let mut profile = MerchantKind::Generic.profile();
+        profile.guaranteed_stock.clear();
+
+        // far too poor to stock any armor
+        profile.category_weights = [(ItemCategory::Armor, 1)].into();
+        let mut merchant = Merchant::new(1, 1).with_profile(profile.clone());
+        let fill = merchant
+            .generate_inventory_with_seed(&pool, 1)
+            .await
+            .unwrap();
+        assert_eq!(fill.stop, FillStop::NothingAffordable);
+        assert!(merchant.is_empty());
+
+        // a free item never uses up the budget, so only the attempt limit stops it
+        let csv = "name,pfs,source,rarity,trait,item_category,item_subcategory,level,price,bulk,usage,spoilers
+Free Snare,,Homebrew,Common,,Snares,,0,0 cp,L,,";
+        database::import_reader(&pool, csv.as_bytes(), "homebrew")
+            .await
+            .unwrap();
+        profile.category_weights = [(ItemCategory::Snares, 1)].into();
+        profile.subcategory_weights.clear();
+        profile.max_fill_attempts = 50;
+        let mut merchant = Merchant::new(1, 0)
+            .with_profile(profile.clone())
+            .with_item_levels(0..=0);
+        let fill = merchant
+            .generate_inventory_with_seed(&pool, 1)
+            .await
+            .unwrap();
+        assert_eq!(fill.stop, FillStop::AttemptsExhausted);
+        assert_eq!(fill.attempts, 50);
+
+        // half the budget, within the tolerance
+        profile = MerchantKind::Generic.profile();
+        profile.guaranteed_stock.clear();
+        profile.fill_target = 0.5;
+        let mut merchant = Merchant::by_level(4).with_profile(profile);
+        let fill = merchant
+            .generate_inventory_with_seed(&pool, 1)
+            .await
+            .unwrap();
+        assert!(fill.is_complete());
+        assert_eq!(fill.budget.target(), merchant.wealth() / 2);
+        assert_eq!(merchant.inventory().value_cp(), fill.budget.spent());
+        assert!(fill.budget.spent() <= fill.budget.target());
+    }
+
+    #[tokio::test]
+    async fn guaranteed_stock_rules() {
+        let pool = test_pool().await;
+        let catalog = ItemCatalog::load(&pool).await.unwrap();
+
+        let mut store = Merchant::by_level(5).with_kind(MerchantKind::GeneralStore);
+        store.generate_inventory_from(&catalog, 3);
+        assert!(store.inventory().get("Torch").unwrap().quantity >= 5);
+        assert!(store.inventory().get("Rope").unwrap().quantity >= 1);
+        assert!(store.inventory().get("Rations").is_some());
+        assert!(store
+            .inventory()
+            .iter()
+            .any(|s| s.item.name.starts_with("Healing Potion") && s.item.level <= 5));
+        // past the first, rations only take their share of a poor shop's wealth
+        let rations = catalog.get("Rations").unwrap().price.as_ref().unwrap();
+        for seed in 0..10 {
+            let mut shop = Merchant::by_level(0);
+            shop.generate_inventory_from(&catalog, seed);
+            let share = shop.wealth() / 24;
+            let stocked = shop.inventory().get("Rations").unwrap();
+            assert!(stocked.value_cp().unwrap() <= share.max(rations.as_cp()));
+        }
+
+        let potions = &MerchantKind::GeneralStore.guaranteed_stock()[3];
+        let minor = catalog.get("Healing Potion (Minor)").unwrap();
+        let lesser = catalog.get("Healing Potion (Lesser)").unwrap();
+        assert!(potions.covers(minor, 1));
+        assert!(!potions.covers(lesser, 1));
+        assert!(!potions.covers(catalog.get("Rations").unwrap(), 1));
+
+        // higher priority rules are paid for first, and missing items are skipped
+        let mut profile = MerchantKind::Generic.profile();
+        profile.category_weights.clear();
+        profile.guaranteed_stock = vec![
+            GuaranteedStock::named("Not A Real Item", 1),
+            GuaranteedStock::named("Rope", 2),
+            GuaranteedStock {
+                priority: 1,
+                ..GuaranteedStock::named("Rations", 1)
+            },
+        ];
+        let mut merchant = Merchant::new(100, 1).with_profile(profile);
+        merchant.generate_inventory_from(&catalog, 1);
+        assert_eq!(merchant.inventory().get("Rations").unwrap().quantity, 1);
+        assert_eq!(merchant.inventory().get("Rope").unwrap().quantity, 1);
+    }
+
+    #[tokio::test]
+    async fn subcategories_are_weighted() {
+        let pool = test_pool().await;
+        let catalog = ItemCatalog::load(&pool).await.unwrap();
+        let subcategory_count = |merchant: &Merchant, subcategory: &str| {
+            merchant
+                .inventory()
+                .iter()
+                .filter(|s| s.item.item_subcategory == subcategory)
+                .count()
+        };
+
+        // there are twice as many elixirs as poisons, but alchemists favor
+        // elixirs four to one
+        let (mut elixirs, mut poisons) = (0, 0);
+        for seed in 0..5 {
+            let mut alchemist = Merchant::by_level(8).with_kind(MerchantKind::Alchemist);
+            alchemist.generate_inventory_from(&catalog, seed);
+            elixirs += subcategory_count(&alchemist, "Alchemical Elixirs");
+            poisons += subcategory_count(&alchemist, "Alchemical Poisons");
+        }
+        assert!(
+            elixirs > 2 * poisons,
+            "{} elixirs, {} poisons",
+            elixirs,
+            poisons
+        );
+
+        // without any weights, the few shields with no subcategory aren't
+        // picked as often as the many base shields
+        let (mut unsorted, mut base) = (0, 0);
+        for seed in 0..10 {
+            let mut shop = Merchant::by_level(4).with_profile(MerchantProfile {
+                category_weights: [(ItemCategory::Shields, 1)].into(),
+                ..MerchantKind::Generic.profile()
+            });
+            shop.generate_inventory_from(&catalog, seed);
+            unsorted += subcategory_count(&shop, "");
+            base += subcategory_count(&shop, "Base Shields");
+        }
+        assert!(base > 2 * unsorted, "{} base, {} unsorted", base, unsorted);
+
+        let mut profile = MerchantKind::Alchemist.profile();
+        profile
+            .subcategory_weights
+            .get_mut(&ItemCategory::AlchemicalItems)
+            .unwrap()
+            .insert("Alchemical Elixirs".to_string(), 0);
+        let mut alchemist = Merchant::by_level(8).with_profile(profile);
+        alchemist.generate_inventory_from(&catalog, 1);
+        assert_eq!(subcategory_count(&alchemist, "Alchemical Elixirs"), 0);
+    }
+
+    #[tokio::test]
+    async fn trait_filters() {
+        let pool = test_pool().await;
+        let catalog = ItemCatalog::load(&pool).await.unwrap();
+
+        let mut merchant = Merchant::by_level(6)
+            .with_excluded_traits(["Tech", "magical"])
+            .with_trait_weight("Alchemical", 0.0);
+        merchant.generate_inventory_from(&catalog, 2);
+        assert!(merchant.len() > 10);
+        assert!(merchant.inventory().iter().all(|s| {
+            !s.item.has_trait("Tech")
+                && !s.item.has_trait("Magical")
+                && !s.item.has_trait("Alchemical")
+        }));
+
+        let mut alchemist = Merchant::by_level(6)
+            .with_kind(MerchantKind::Alchemist)
+            .with_included_traits(["Elixir", "Bomb"])
+            .with_required_traits(["Consumable"]);
+        alchemist.generate_inventory_from(&catalog, 2);
+        // guaranteed stock is named, so it ignores everything but exclusions
+        assert!(alchemist.inventory().get("Rations").is_some());
+        for stack in alchemist.inventory().iter() {
+            let item = &stack.item;
+            if item.name != "Rations" {
+                assert!(item.has_trait("Elixir") || item.has_trait("Bomb"));
+                assert!(item.has_trait("Consumable"));
             }
         }
+    }
 
-        Ok(())
+    #[tokio::test]
+    async fn buy_and_sell() {
+        let pool = test_pool().await;
+        let rations = database::get_by_name(&pool, "Rations")
+            .await
+            .unwrap()
+            .unwrap();
+        let mut merchant = Merchant::new(100, 1);
+
+        // rations are 4 sp, so the merchant pays 2 sp each
+        assert!(merchant.sell(rations.clone(), u32::MAX, Some(100)).is_err());
+        assert!(merchant.sell(rations.clone(), u32::MAX, None).is_err());
+        assert!(merchant.sell(rations.clone(), 0, None).is_err());
+        assert!(merchant.sell(rations.clone(), 1, Some(-500)).is_err());
+        assert_eq!(merchant.wealth(), 100);
+        let sale = merchant.sell(rations.clone(), 5, None).unwrap();
+        assert_eq!(sale.price_cp, 100);
+        assert_eq!(merchant.wealth(), 0);
+        assert!(merchant.sell(rations, 1, None).is_err());
+
+        let purchase = merchant.buy("rations", 2).unwrap();
+        assert_eq!(purchase.kind, TransactionKind::Buy);
+        assert_eq!(purchase.price_cp, 80);
+        assert_eq!(merchant.wealth(), 80);
+        assert_eq!(merchant.len(), 3);
+        assert!(merchant.buy("Rations", 4).is_err());
+        assert!(merchant.buy("Rations", 0).is_err());
+        assert_eq!(merchant.transactions().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn restock_over_time() {
+        let pool = test_pool().await;
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut merchant = Merchant::by_level(5);
+        merchant
+            .generate_inventory_with_seed(&pool, 7)
+            .await
+            .unwrap();
+        let original = merchant.clone();
+
+        let catalog = ItemCatalog::load(&pool).await.unwrap();
+        let summary = merchant.restock_with_rng(&catalog, 0, &mut rng).unwrap();
+        assert_eq!(summary.sold, 0);
+        assert_eq!(merchant.inventory(), original.inventory());
+        assert_eq!(merchant.seed(), Some(7));
+
+        // no time passing doesn't undo a purchase
+        let mut bought = merchant.clone();
+        bought.buy("Rations", 2).unwrap();
+        let summary = bought.restock_with_rng(&catalog, 0, &mut rng).unwrap();
+        assert_eq!((summary.sold, summary.added), (0, 0));
+        assert_eq!(
+            bought.inventory().value_cp(),
+            merchant.inventory().value_cp() - 80
+        );
+
+        merchant.buy("Rations", 3).unwrap();
+        let summary = merchant.restock_with_rng(&catalog, 60, &mut rng).unwrap();
+        assert!(summary.sold > 0);
+        assert!(summary.added > 0);
+        assert_eq!(merchant.seed(), None);
+        // the merchant's wealth is almost back to normal after two months
+        assert!((merchant.wealth() - original.wealth()).abs() < 3 * 40);
+
+        // the same seed restocks the same way
+        let mut first = original.clone();
+        let mut second = original.clone();
+        first.restock_with_seed(&pool, 30, 3).await.unwrap();
+        second.restock_with_seed(&pool, 30, 3).await.unwrap();
+        assert_eq!(first, second);
+
+        // empty shelves are only partly refilled after a short wait
+        let mut day = Merchant::by_level(5);
+        let mut month = day.clone();
+        day.restock_with_rng(&catalog, 1, &mut rng).unwrap();
+        month.restock_with_rng(&catalog, 30, &mut rng).unwrap();
+        assert!(
+            day.inventory().value_cp() * 3 < month.inventory().value_cp(),
+            "{} after a day, {} after a month",
+            day.inventory().value_cp(),
+            month.inventory().value_cp()
+        );
     }
 }
@@ -0,0 +1,504 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ops::RangeInclusive,
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+use rand::{distributions::WeightedIndex, prelude::Distribution, Rng};
+
+use crate::{
+    item::{Item, ItemCategory, Rarity},
+    kind::MerchantKind,
+};
+
+pub const DEFAULT_SUBCATEGORY_WEIGHT: u32 = 1;
+pub const DEFAULT_UNCOMMON_CHANCE: f32 = 0.005;
+pub const DEFAULT_RARE_CHANCE: f32 = 0.001;
+pub const DEFAULT_UNIQUE_CHANCE: f32 = 0.0;
+pub const DEFAULT_FILL_TARGET: f32 = 1.0;
+pub const DEFAULT_FILL_TOLERANCE: f32 = 0.02;
+pub const DEFAULT_MAX_FILL_ATTEMPTS: u32 = 10_000;
+pub const DEFAULT_MIN_LEVEL_OFFSET: i32 = -4;
+pub const DEFAULT_MAX_LEVEL_OFFSET: i32 = 1;
+pub const DEFAULT_LEVEL_FALLOFF: f32 = 0.7;
+
+/// Everything that controls what a merchant stocks
+/// Profiles can be loaded from .ron or .toml files, and any field left out
+/// falls back to the generic merchant's value
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct MerchantProfile {
+    /// Relative weight of each category
+    /// Categories left out are never stocked
+    pub category_weights: BTreeMap<ItemCategory, u32>,
+    /// Relative weight of each subcategory within a category
+    /// Subcategory must match string exactly as it appears on AoN, and a
+    /// weight of 0 never stocks it
+    pub subcategory_weights: BTreeMap<ItemCategory, BTreeMap<String, u32>>,
+    /// Weight of any subcategory left out of `subcategory_weights`, in
+    /// categories that list at least one subcategory
+    /// 0 only stocks the subcategories that are listed
+    pub default_subcategory_weight: u32,
+    /// Chance for each item to be upgraded to an uncommon item
+    pub uncommon_chance: f32,
+    /// Chance for each item to be upgraded to a rare item
+    pub rare_chance: f32,
+    /// Chance for each item to be upgraded to a unique item
+    pub unique_chance: f32,
+    /// The fewest items of uncommon or greater rarity to stock, as long as the
+    /// merchant can afford them
+    pub min_uncommon: u32,
+    /// Multiplier applied to the merchant's wealth from the treasure table
+    pub wealth_multiplier: f32,
+    /// Lowest level of item stocked, relative to the merchant's level
+    pub min_level_offset: i32,
+    /// Highest level of item stocked, relative to the merchant's level
+    pub max_level_offset: i32,
+    /// How much less likely an item is to be stocked for each level it is
+    /// away from the merchant's level, from 0 to 1
+    /// 1 stocks every level in the window equally
+    pub level_falloff: f32,
+    /// Share of the merchant's wealth to spend on stock, from 0 to 1
+    pub fill_target: f32,
+    /// How close to the fill target is close enough, as a share of the target
+    pub fill_tolerance: f32,
+    /// The most items to pick before giving up on reaching the fill target
+    pub max_fill_attempts: u32,
+    /// Items that are stocked before anything else
+    pub guaranteed_stock: Vec<GuaranteedStock>,
+    /// Items with any of these traits are never stocked (case insensitive)
+    pub excluded_traits: Vec<String>,
+    /// If not empty, only items with at least one of these traits are
+    /// stocked (case insensitive)
+    pub included_traits: Vec<String>,
+    /// Only items with every one of these traits are stocked (case insensitive)
+    pub required_traits: Vec<String>,
+    /// Multiplier on the chance to stock items with each trait, e.g.
+    /// `Magical = 0.5` (case insensitive)
+    /// 0 never stocks items with the trait
+    pub trait_weights: BTreeMap<String, f32>,
+}
+
+/// A rule for an item a merchant always keeps some of
+///
+/// A rule either names one item or, with `filter`, stocks any item that
+/// matches, like the best healing potion the merchant can sell.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct GuaranteedStock {
+    /// Name must match string exactly as it appears on AoN
+    /// Ignored when `filter` is set
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub name: String,
+    /// Stock any item that matches instead of the item called `name`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<StockFilter>,
+    /// The most of this item the merchant will stock
+    pub max_quantity: u32,
+    /// The fewest of this item the merchant will stock, as long as they can
+    /// afford it, even past their `budget_share`
+    /// The quantity is rolled between this and `max_quantity`, and defaults to
+    /// `max_quantity`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_quantity: Option<u32>,
+    /// Added to both quantities for each of the merchant's levels
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub quantity_per_level: f32,
+    /// Fraction of the merchant's wealth that may be spent on this item
+    #[serde(default = "full_share")]
+    pub budget_share: f32,
+    /// Rules with a higher priority are stocked first, so they have first
+    /// claim on the merchant's wealth
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub priority: i32,
+}
+
+/// Which items a guaranteed stock rule can pick from
+/// Only common items the profile allows are picked
+#[derive(Debug, Default, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct StockFilter {
+    /// Only items whose name contains this (case insensitive), e.g.
+    /// "Healing Potion"
+    pub name_contains: Option<String>,
+    pub category: Option<ItemCategory>,
+    /// Subcategory must match string exactly as it appears on AoN
+    pub subcategory: Option<String>,
+    /// Only items with every one of these traits (case insensitive)
+    pub traits: Vec<String>,
+    /// Highest level of item, relative to the merchant's level
+    pub max_level_offset: i32,
+}
+
+fn full_share() -> f32 {
+    1.0
+}
+
+fn is_zero<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
+impl Default for GuaranteedStock {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            filter: None,
+            max_quantity: 1,
+            min_quantity: None,
+            quantity_per_level: 0.0,
+            budget_share: full_share(),
+            priority: 0,
+        }
+    }
+}
+
+impl GuaranteedStock {
+    /// A rule for up to `max_quantity` of the item called `name`
+    pub fn named<S: Into<String>>(name: S, max_quantity: u32) -> Self {
+        Self {
+            name: name.into(),
+            max_quantity,
+            ..Self::default()
+        }
+    }
+
+    /// A rule for up to `max_quantity` of any item matching `filter`
+    pub fn matching(filter: StockFilter, max_quantity: u32) -> Self {
+        Self {
+            filter: Some(filter),
+            max_quantity,
+            ..Self::default()
+        }
+    }
+
+    /// How many a merchant of `level` stocks
+    pub fn quantity(&self, level: i32) -> RangeInclusive<u32> {
+        let extra = (self.quantity_per_level * level.max(0) as f32).max(0.0) as u32;
+        let max = self.max_quantity.saturating_add(extra);
+        let min = self
+            .min_quantity
+            .map_or(max, |min| min.saturating_add(extra).min(max));
+        min..=max
+    }
+
+    /// Whether `item` counts towards this rule for a merchant of `level`
+    pub fn covers(&self, item: &Item, level: i32) -> bool {
+        match &self.filter {
+            Some(filter) => filter.matches(item, level),
+            None => item.name == self.name,
+        }
+    }
+}
+
+impl StockFilter {
+    /// Whether `item` matches for a merchant of `level`
+    pub fn matches(&self, item: &Item, level: i32) -> bool {
+        item.rarity == Rarity::Common
+            && item.level <= level + self.max_level_offset
+            && self.category.is_none_or(|c| item.item_category == c)
+            && self
+                .subcategory
+                .as_ref()
+                .is_none_or(|s| item.item_subcategory == *s)
+            && self
+                .name_contains
+                .as_ref()
+                .is_none_or(|n| item.name.to_lowercase().contains(&n.to_lowercase()))
+            && self.traits.iter().all(|t| item.has_trait(t))
+    }
+}
+
+impl Default for MerchantProfile {
+    fn default() -> Self {
+        MerchantKind::Generic.profile()
+    }
+}
+
+impl MerchantProfile {
+    /// Read a profile from a .ron or .toml file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read profile {}", path.display()))?;
+        let profile = match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => toml::from_str(&text)
+                .with_context(|| format!("Failed to parse profile {}", path.display()))?,
+            _ => ron::from_str(&text)
+                .with_context(|| format!("Failed to parse profile {}", path.display()))?,
+        };
+        Ok(profile)
+    }
+
+    /// The levels of item a merchant of `level` stocks
+    pub fn level_window(&self, level: i32) -> RangeInclusive<i32> {
+        (level + self.min_level_offset)..=(level + self.max_level_offset)
+    }
+
+    /// Relative chance for a merchant of `level` to stock an item of
+    /// `item_level`, favoring items near the merchant's level
+    pub fn level_weight(&self, level: i32, item_level: i32) -> f64 {
+        // never quite zero, so there's always something to pick
+        let falloff = (self.level_falloff as f64).clamp(0.001, 1.0);
+        falloff.powi((level - item_level).abs())
+    }
+
+    /// Pick the rarity of the next item to stock
+    /// Each rarity has its own chance, and the rest of the time it's common
+    pub fn pick_rarity<R: Rng + ?Sized>(&self, rng: &mut R) -> Rarity {
+        let roll: f32 = rng.gen();
+        let mut threshold = 0.0;
+        for (rarity, chance) in [
+            (Rarity::Unique, self.unique_chance),
+            (Rarity::Rare, self.rare_chance),
+            (Rarity::Uncommon, self.uncommon_chance),
+        ] {
+            threshold += chance.max(0.0);
+            if roll < threshold {
+                return rarity;
+            }
+        }
+        Rarity::Common
+    }
+
+    /// Categories this profile can stock
+    pub fn categories(&self) -> Vec<ItemCategory> {
+        self.category_weights
+            .iter()
+            .filter(|(_, weight)| **weight > 0)
+            .map(|(category, _)| *category)
+            .collect()
+    }
+
+    /// Whether the item has one of the profile's excluded traits
+    pub fn excludes(&self, item: &Item) -> bool {
+        self.excluded_traits.iter().any(|t| item.has_trait(t))
+    }
+
+    /// Whether the item is allowed by the profile's trait rules
+    pub fn allows(&self, item: &Item) -> bool {
+        !self.excludes(item)
+            && (self.included_traits.is_empty()
+                || self.included_traits.iter().any(|t| item.has_trait(t)))
+            && self.required_traits.iter().all(|t| item.has_trait(t))
+            && self.trait_weight(item) > 0.0
+    }
+
+    /// Multiplier on the chance to stock `item` from the traits it has
+    pub fn trait_weight(&self, item: &Item) -> f64 {
+        self.trait_weights
+            .iter()
+            .filter(|(t, _)| item.has_trait(t))
+            .map(|(_, weight)| weight.max(0.0) as f64)
+            .product()
+    }
+
+    /// Pick the category of the next item to stock
+    /// Returns `None` if no category has a positive weight
+    pub fn pick_category<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<ItemCategory> {
+        self.pick_category_where(rng, |_| true)
+    }
+
+    /// Pick the category of the next item to stock out of those `filter`
+    /// accepts
+    /// Returns `None` if no accepted category has a positive weight
+    pub fn pick_category_where<R: Rng + ?Sized, F: Fn(ItemCategory) -> bool>(
+        &self,
+        rng: &mut R,
+        filter: F,
+    ) -> Option<ItemCategory> {
+        let weights = self
+            .category_weights
+            .iter()
+            .filter(|(category, _)| filter(**category))
+            .collect::<Vec<_>>();
+        let dist = WeightedIndex::new(weights.iter().map(|(_, weight)| **weight)).ok()?;
+        Some(*weights[dist.sample(rng)].0)
+    }
+
+    /// Relative weight of `subcategory` within `category`
+    pub fn subcategory_weight(&self, category: ItemCategory, subcategory: &str) -> u32 {
+        self.subcategory_weights
+            .get(&category)
+            .and_then(|weights| weights.get(subcategory))
+            .copied()
+            .unwrap_or(self.default_subcategory_weight)
+    }
+
+    /// Pick the subcategory of the next item to stock within `category` out
+    /// of `subcategories`, so a subcategory with many items doesn't crowd out
+    /// the rest
+    /// Returns `None` if the profile doesn't weight any subcategory of
+    /// `category`, so items are picked from the whole category, or if none of
+    /// them has a positive weight
+    pub fn pick_subcategory<'a, I, R>(
+        &self,
+        category: ItemCategory,
+        subcategories: I,
+        rng: &mut R,
+    ) -> Option<&'a str>
+    where
+        I: IntoIterator<Item = &'a str>,
+        R: Rng + ?Sized,
+    {
+        if self
+            .subcategory_weights
+            .get(&category)
+            .is_none_or(|weights| weights.is_empty())
+        {
+            return None;
+        }
+        let subcategories = subcategories.into_iter().collect::<BTreeSet<_>>();
+        let weights = subcategories
+            .iter()
+            .map(|s| (*s, self.subcategory_weight(category, s)))
+            .collect::<Vec<_>>();
+        let dist = WeightedIndex::new(weights.iter().map(|(_, weight)| *weight)).ok()?;
+        Some(weights[dist.sample(rng)].0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        item::{ItemCategory, Rarity},
+        kind::MerchantKind,
+        profile::{GuaranteedStock, MerchantProfile},
+    };
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn partial_toml_profile() {
+        let profile: MerchantProfile = toml::from_str(
+            r#"
+            rare_chance = 0.05
+            excluded_traits = ["Tech"]
+
+            [category_weights]
+            Weapons = 10
+            Armor = 5
+
+            [subcategory_weights.Weapons]
+            "Base Weapons" = 1
+
+            [[guaranteed_stock]]
+            name = "Torch"
+            max_quantity = 5
+            budget_share = 0.01
+            "#,
+        )
+        .unwrap();
+
+        let generic = MerchantKind::Generic.profile();
+        assert_eq!(profile.rare_chance, 0.05);
+        assert_eq!(profile.uncommon_chance, generic.uncommon_chance);
+        assert_eq!(
+            profile.categories(),
+            vec![ItemCategory::Weapons, ItemCategory::Armor]
+        );
+        assert_eq!(profile.guaranteed_stock[0].name, "Torch");
+        assert_eq!(profile.guaranteed_stock[0].quantity(3), 5..=5);
+    }
+
+    #[test]
+    fn guaranteed_stock_filters() {
+        let profile: MerchantProfile = toml::from_str(
+            r#"
+            [[guaranteed_stock]]
+            max_quantity = 2
+            min_quantity = 1
+            quantity_per_level = 0.5
+            priority = 2
+
+            [guaranteed_stock.filter]
+            name_contains = "healing potion"
+            traits = ["Potion"]
+            "#,
+        )
+        .unwrap();
+        let rule = &profile.guaranteed_stock[0];
+        assert_eq!(rule.budget_share, 1.0);
+        assert_eq!(rule.quantity(0), 1..=2);
+        assert_eq!(rule.quantity(4), 3..=4);
+
+        let huge = GuaranteedStock {
+            min_quantity: Some(u32::MAX - 1),
+            quantity_per_level: 1e9,
+            ..GuaranteedStock::named("Rations", u32::MAX)
+        };
+        assert_eq!(huge.quantity(30), u32::MAX..=u32::MAX);
+
+        // a rule saved by this version reads back the same
+        let ron = ron::to_string(&profile).unwrap();
+        assert_eq!(ron::from_str::<MerchantProfile>(&ron).unwrap(), profile);
+    }
+
+    #[test]
+    fn every_rarity_can_be_picked() {
+        let profile = MerchantProfile {
+            uncommon_chance: 0.2,
+            rare_chance: 0.1,
+            unique_chance: 0.05,
+            ..MerchantProfile::default()
+        };
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut counts = std::collections::HashMap::new();
+        for _ in 0..10000 {
+            *counts.entry(profile.pick_rarity(&mut rng)).or_insert(0) += 1;
+        }
+        // the rare chance is smaller than the uncommon chance, but still fires
+        assert!((800..1200).contains(&counts[&Rarity::Rare]));
+        assert!((1700..2300).contains(&counts[&Rarity::Uncommon]));
+        assert!((300..700).contains(&counts[&Rarity::Unique]));
+        assert!((6000..7000).contains(&counts[&Rarity::Common]));
+
+        let common_only = MerchantProfile {
+            uncommon_chance: 0.0,
+            rare_chance: 0.0,
+            ..MerchantProfile::default()
+        };
+        assert!((0..100).all(|_| common_only.pick_rarity(&mut rng) == Rarity::Common));
+    }
+
+    #[test]
+    fn unlisted_subcategories_use_the_default_weight() {
+        let weights = [
+            ("Base Weapons".to_string(), 3),
+            ("Beast Guns".to_string(), 0),
+        ];
+        let mut profile = MerchantProfile {
+            subcategory_weights: [(ItemCategory::Weapons, weights.into())].into(),
+            ..MerchantProfile::default()
+        };
+        let subcategories = [
+            "Base Weapons",
+            "Beast Guns",
+            "Precious Material Weapons",
+            "Precious Material Weapons",
+        ];
+
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut counts = std::collections::HashMap::new();
+        for _ in 0..4000 {
+            let picked = profile
+                .pick_subcategory(ItemCategory::Weapons, subcategories, &mut rng)
+                .unwrap();
+            *counts.entry(picked).or_insert(0) += 1;
+        }
+        // repeats don't count twice
+        assert!((2800..3200).contains(&counts["Base Weapons"]));
+        assert!((800..1200).contains(&counts["Precious Material Weapons"]));
+        assert!(!counts.contains_key("Beast Guns"));
+
+        // categories without any weights don't pick a subcategory
+        assert_eq!(
+            profile.pick_subcategory(ItemCategory::Shields, ["", "Base Shields"], &mut rng),
+            None
+        );
+
+        profile.default_subcategory_weight = 0;
+        assert_eq!(
+            profile.pick_subcategory(ItemCategory::Weapons, ["Beast Guns", "Drugs"], &mut rng),
+            None
+        );
+    }
+}
@@ -0,0 +1,291 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use sqlx::{Pool, Sqlite};
+
+use crate::{catalog::ItemCatalog, export, kind::MerchantKind, merchant::Merchant, wealth};
+
+/// How big a settlement is, which decides how many shops it has and how
+/// wealthy they are
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    Eq,
+    PartialEq,
+    Ord,
+    PartialOrd,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+    enum_iterator::Sequence,
+)]
+pub enum SettlementSize {
+    Village,
+    #[default]
+    Town,
+    City,
+}
+
+impl SettlementSize {
+    /// The shops in a settlement of this size, as the kind of shop and how many
+    /// levels below the settlement it is
+    ///
+    /// A settlement's level is the highest level of item commonly for sale
+    /// there, so the best stocked shops match it and the rest fall below it.
+    pub fn shops(&self) -> &'static [(MerchantKind, i32)] {
+        use MerchantKind::*;
+        match self {
+            SettlementSize::Village => &[(GeneralStore, 0), (Blacksmith, 1)],
+            SettlementSize::Town => &[
+                (GeneralStore, 0),
+                (Blacksmith, 0),
+                (Alchemist, 1),
+                (Generic, 2),
+            ],
+            SettlementSize::City => &[
+                (GeneralStore, 0),
+                (Blacksmith, 0),
+                (Alchemist, 0),
+                (Arcanist, 0),
+                (Generic, 1),
+                (Generic, 2),
+            ],
+        }
+    }
+
+    /// Multiplier applied to the wealth of every merchant in the settlement
+    pub fn wealth_multiplier(&self) -> f32 {
+        match self {
+            SettlementSize::Village => 0.5,
+            SettlementSize::Town => 1.0,
+            SettlementSize::City => 1.5,
+        }
+    }
+
+    /// The fewest uncommon items each shop stocks, so big cities always have
+    /// something unusual for sale
+    pub fn min_uncommon(&self) -> u32 {
+        match self {
+            SettlementSize::Village | SettlementSize::Town => 0,
+            SettlementSize::City => 2,
+        }
+    }
+}
+
+impl AsRef<str> for SettlementSize {
+    fn as_ref(&self) -> &str {
+        match self {
+            SettlementSize::Village => "village",
+            SettlementSize::Town => "town",
+            SettlementSize::City => "city",
+        }
+    }
+}
+
+impl std::fmt::Display for SettlementSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_ref())
+    }
+}
+
+impl std::str::FromStr for SettlementSize {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let normalized = s.trim().to_lowercase();
+        enum_iterator::all::<SettlementSize>()
+            .find(|size| size.as_ref() == normalized)
+            .with_context(|| {
+                format!(
+                    "Unknown settlement size: {} (expected one of: village, town, city)",
+                    s
+                )
+            })
+    }
+}
+
+/// Every merchant in a settlement, generated together
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Settlement {
+    level: i32,
+    size: SettlementSize,
+    seed: Option<u64>,
+    merchants: Vec<Merchant>,
+}
+
+impl Settlement {
+    /// An empty settlement
+    /// Fails if the level is outside the range merchants can be generated for
+    pub fn new(level: i32, size: SettlementSize) -> Result<Self> {
+        wealth::validate_level(level).context("Invalid settlement level")?;
+        Ok(Self {
+            level,
+            size,
+            seed: None,
+            merchants: vec![],
+        })
+    }
+
+    pub fn level(&self) -> i32 {
+        self.level
+    }
+
+    pub fn size(&self) -> SettlementSize {
+        self.size
+    }
+
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    pub fn merchants(&self) -> &[Merchant] {
+        &self.merchants
+    }
+
+    pub fn merchants_mut(&mut self) -> &mut [Merchant] {
+        &mut self.merchants
+    }
+
+    /// "Level 5 Town"
+    pub fn name(&self) -> String {
+        let size = self.size.to_string();
+        let mut chars = size.chars();
+        let size = match chars.next() {
+            Some(first) => first.to_uppercase().chain(chars).collect(),
+            None => size,
+        };
+        format!("Level {} {}", self.level, size)
+    }
+
+    /// Generate every shop in the settlement from a random seed
+    pub async fn generate(&mut self, pool: &Pool<Sqlite>) -> Result<()> {
+        self.generate_with_seed(pool, rand::random()).await
+    }
+
+    /// Generate every shop in the settlement from `seed`
+    /// Each merchant gets their own seed drawn from it, so any one of them can
+    /// be regenerated on their own
+    pub async fn generate_with_seed(&mut self, pool: &Pool<Sqlite>, seed: u64) -> Result<()> {
+        let catalog = ItemCatalog::load(pool).await?;
+        self.generate_from(&catalog, seed);
+        Ok(())
+    }
+
+    /// Generate every shop in the settlement from `seed` using items from
+    /// `catalog`, which can be shared between settlements
+    pub fn generate_from(&mut self, catalog: &ItemCatalog, seed: u64) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        self.seed = Some(seed);
+        self.merchants.clear();
+
+        for (kind, offset) in self.size.shops() {
+            // smaller shops bottom out at level 1, unless the whole settlement is
+            // level 0
+            let level = (self.level - offset).max(self.level.min(1));
+            let mut profile = kind.profile();
+            profile.wealth_multiplier = self.size.wealth_multiplier();
+            profile.min_uncommon = profile.min_uncommon.max(self.size.min_uncommon());
+            let mut merchant = Merchant::by_level(level)
+                .with_kind(*kind)
+                .with_profile(profile);
+            merchant.generate_inventory_from(catalog, rng.gen());
+            self.merchants.push(merchant);
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let ron = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        ron::from_str(&ron).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let filename = format!(
+            "{}_{}.ron",
+            self.size,
+            chrono::offset::Local::now().format("%Y-%m-%d_%I:%M %p")
+        );
+        self.save_to(filename)
+    }
+
+    pub fn save_to<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let ron = ron::to_string(self)?;
+        std::fs::write(path, ron)?;
+        Ok(())
+    }
+
+    pub fn markdown(&self) -> String {
+        let mut s = format!("# {}  \n", self.name());
+        for merchant in self.merchants.iter() {
+            s.push_str(&format!("\n# {}  \n", merchant.name()));
+            s.push_str(&merchant.markdown());
+        }
+        s
+    }
+
+    /// The settlement in the schema documented in `export::json`
+    pub fn json(&self) -> Result<String> {
+        export::json::settlement_to_string(self)
+    }
+
+    /// Every merchant as a Foundry VTT loot actor, see `export::foundry`
+    pub fn foundry(&self) -> Result<String> {
+        export::foundry::settlement_to_string(self)
+    }
+}
+
+impl std::fmt::Display for Settlement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}", self.name())?;
+        for merchant in self.merchants.iter() {
+            writeln!(f, "\n========== {} ==========", merchant.name())?;
+            write!(f, "{}", merchant)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        database::test_pool,
+        settlement::{Settlement, SettlementSize},
+    };
+
+    #[tokio::test]
+    async fn shops_follow_settlement_level() {
+        let pool = test_pool().await;
+        assert!(Settlement::new(-1, SettlementSize::Town).is_err());
+        assert_eq!(
+            "City".parse::<SettlementSize>().unwrap(),
+            SettlementSize::City
+        );
+
+        let mut village = Settlement::new(1, SettlementSize::Village).unwrap();
+        village.generate_with_seed(&pool, 3).await.unwrap();
+        assert_eq!(
+            village.merchants().len(),
+            SettlementSize::Village.shops().len()
+        );
+        assert!(village.merchants().iter().all(|m| m.level() == 1));
+
+        let mut town = Settlement::new(6, SettlementSize::Town).unwrap();
+        town.generate_with_seed(&pool, 3).await.unwrap();
+        let levels = town
+            .merchants()
+            .iter()
+            .map(|m| m.level())
+            .collect::<Vec<_>>();
+        assert_eq!(levels, vec![6, 6, 5, 4]);
+        assert!(town.merchants().iter().all(|m| !m.is_empty()));
+
+        let ron = ron::to_string(&town).unwrap();
+        let loaded: Settlement = ron::from_str(&ron).unwrap();
+        assert_eq!(loaded, town);
+    }
+}
@@ -0,0 +1,127 @@
+use std::{path::Path, sync::OnceLock};
+
+use anyhow::{bail, Context, Result};
+
+/// The lowest level a merchant can be generated at
+pub const MIN_LEVEL: i32 = 0;
+/// The highest level a merchant can be generated at
+pub const MAX_LEVEL: i32 = 30;
+
+/// The bundled treasure table
+const WEALTH_TOML: &str = include_str!("../assets/wealth.toml");
+
+/// Fail with a readable error if merchants can't be generated at `level`
+pub fn validate_level(level: i32) -> Result<()> {
+    if !(MIN_LEVEL..=MAX_LEVEL).contains(&level) {
+        bail!(
+            "Level must be between {} and {}, got {}",
+            MIN_LEVEL,
+            MAX_LEVEL,
+            level
+        );
+    }
+    Ok(())
+}
+
+/// Total party treasure in gp by level
+///
+/// Levels past either end of the table are extrapolated, growing at the same
+/// rate as the last two levels at that end.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct WealthTable {
+    first_level: i32,
+    gp: Vec<i64>,
+}
+
+impl Default for WealthTable {
+    /// The Treasure by Level table, see `assets/wealth.toml`
+    fn default() -> Self {
+        Self::bundled().clone()
+    }
+}
+
+impl std::str::FromStr for WealthTable {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let table: WealthTable = toml::from_str(s).context("Failed to parse wealth table")?;
+        if table.gp.len() < 2 {
+            bail!("Wealth table needs at least two levels");
+        }
+        if table.gp.iter().any(|gp| *gp <= 0) {
+            bail!("Wealth table must be positive at every level");
+        }
+        Ok(table)
+    }
+}
+
+impl WealthTable {
+    /// The bundled table, parsed once
+    pub fn bundled() -> &'static WealthTable {
+        static TABLE: OnceLock<WealthTable> = OnceLock::new();
+        TABLE.get_or_init(|| {
+            WEALTH_TOML
+                .parse()
+                .expect("bundled wealth table should be valid")
+        })
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let toml = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        toml.parse()
+            .with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    /// The party treasure in gp at `level`
+    pub fn gp(&self, level: i32) -> i64 {
+        let last_level = self.first_level + self.gp.len() as i32 - 1;
+        let (edge, next, steps) = if level < self.first_level {
+            (self.gp[0], self.gp[1], self.first_level - level)
+        } else if level > last_level {
+            let len = self.gp.len();
+            (self.gp[len - 1], self.gp[len - 2], level - last_level)
+        } else {
+            return self.gp[(level - self.first_level) as usize];
+        };
+
+        // beyond the table, keep growing (or shrinking) at the rate of the
+        // two levels at that edge
+        let ratio = edge as f64 / next as f64;
+        (edge as f64 * ratio.powi(steps)).round() as i64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        merchant::Merchant,
+        wealth::{validate_level, WealthTable},
+    };
+
+    #[test]
+    fn extrapolates_past_the_table() {
+        let table = WealthTable::default();
+        assert_eq!(table.gp(1), 175);
+        assert_eq!(table.gp(20), 490000);
+        assert_eq!(table.gp(0), 102);
+        assert!(table.gp(21) > table.gp(20));
+        assert!(table.gp(25) > table.gp(24));
+
+        let custom: WealthTable = "first_level = 0\ngp = [10, 20]".parse().unwrap();
+        assert_eq!(custom.gp(0), 10);
+        assert_eq!(custom.gp(3), 80);
+        assert!("first_level = 0\ngp = [10]".parse::<WealthTable>().is_err());
+
+        assert!(validate_level(0).is_ok());
+        assert!(validate_level(25).is_ok());
+        assert!(validate_level(-1).is_err());
+        assert!(validate_level(31).is_err());
+
+        assert_eq!(Merchant::by_level(0).wealth(), 3400);
+        assert!(Merchant::by_level(25).wealth() > Merchant::by_level(20).wealth());
+        let poor = Merchant::by_level(5).with_wealth_multiplier(0.5);
+        assert_eq!(poor.wealth(), Merchant::by_level(5).wealth() / 2);
+    }
+}
@@ -1,5 +1,18 @@
-use clap::{command, Parser};
-use merchant_gen_lib::merchant::Merchant;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use clap::Parser;
+use merchant_gen_lib::{
+    budget::FillSummary,
+    catalog::ItemCatalog,
+    database::{ItemQuery, SortBy},
+    item::{format_cp, Bulk, ItemCategory, Price, Rarity},
+    kind::MerchantKind,
+    merchant::{self, Merchant},
+    profile::MerchantProfile,
+    settlement::{Settlement, SettlementSize},
+    wealth::{self, WealthTable},
+};
 
 #[cfg(debug_assertions)]
 use tracing_subscriber::EnvFilter;
@@ -10,6 +23,10 @@ use tracing_subscriber::EnvFilter;
 struct Cli {
     #[clap(subcommand)]
     pub subcmd: Subcommand,
+    /// Path to the equipment database, created if it doesn't exist
+    /// (defaults to $MERCHANT_DB, then the platform's data directory)
+    #[arg(long = "db", global = true)]
+    pub db: Option<PathBuf>,
 }
 
 #[derive(Debug, Parser)]
@@ -17,66 +34,575 @@ enum Subcommand {
     #[clap(name = "gen")]
     /// Generate a new merchant inventory
     Generate {
+        /// The merchant's level, from 0 to 30
         level: i32,
+        /// Give the merchant this much wealth in gp instead of the usual for
+        /// their level
+        #[arg(
+            long = "wealth",
+            conflicts_with = "wealth_table",
+            value_parser = clap::value_parser!(i32).range(0..=merchant::MAX_GP as i64)
+        )]
+        wealth: Option<i32>,
+        /// Multiply the merchant's wealth, e.g. 0.5 for a poor village
+        #[arg(long = "wealth-multiplier", default_value_t = 1.0, value_parser = parse_multiplier)]
+        wealth_multiplier: f32,
+        /// Read wealth by level from a toml file instead of the Treasure by
+        /// Level table
+        #[arg(long = "wealth-table")]
+        wealth_table: Option<PathBuf>,
+        /// Lowest level of item to stock (defaults to 4 below the merchant)
+        #[arg(long = "min-level")]
+        min_level: Option<i32>,
+        /// Highest level of item to stock (defaults to 1 above the merchant)
+        #[arg(long = "max-level")]
+        max_level: Option<i32>,
+        /// Only stock items with at least one of these traits, e.g. "Elixir"
+        /// (repeatable)
+        #[arg(long = "include-trait")]
+        include_traits: Vec<String>,
+        /// Only stock items with every one of these traits (repeatable)
+        #[arg(long = "require-trait")]
+        require_traits: Vec<String>,
+        /// Never stock items with this trait, e.g. "Tech" (repeatable)
+        #[arg(long = "exclude-trait")]
+        exclude_traits: Vec<String>,
+        /// Multiply the chance to stock items with a trait, e.g. "Magical=0.5"
+        /// (repeatable)
+        #[arg(long = "trait-weight", value_parser = parse_trait_weight)]
+        trait_weights: Vec<(String, f32)>,
+        /// The type of shop to generate
+        /// (generic, blacksmith, alchemist, arcanist, general-store)
+        #[arg(long = "kind", short, default_value_t = MerchantKind::Generic)]
+        kind: MerchantKind,
+        /// Generate using a custom profile (.ron or .toml) instead of the kind's
+        #[arg(long = "profile", short)]
+        profile: Option<String>,
+        /// Seed the generator to reproduce a previous inventory
+        #[arg(long = "seed")]
+        seed: Option<u64>,
         /// Save the merchant to a .ron file
         #[arg(long = "save", short)]
         save: bool,
-        /// Format Stdout as markdown
-        #[arg(long = "markdown", short)]
+        /// Format Stdout as markdown (same as --format markdown)
+        #[arg(long = "markdown", short, conflicts_with = "format")]
         markdown: bool,
+        /// Format Stdout as text, markdown, json or foundry
+        #[arg(long = "format", short, value_enum, default_value_t = Format::Text)]
+        format: Format,
+    },
+
+    /// Generate every shop in a settlement at once
+    Town {
+        /// The settlement level, the highest level of item commonly for sale
+        #[arg(long = "level", short)]
+        level: i32,
+        /// village, town or city
+        #[arg(long = "size", default_value_t = SettlementSize::Town)]
+        size: SettlementSize,
+        /// Seed the generator to reproduce a previous settlement
+        #[arg(long = "seed")]
+        seed: Option<u64>,
+        /// Save the settlement to a .ron file
+        #[arg(long = "save", short)]
+        save: bool,
+        /// Format Stdout as text, markdown, json or foundry
+        #[arg(long = "format", short, value_enum, default_value_t = Format::Text)]
+        format: Format,
     },
 
     /// Load and display an existing merchant
-    Load { filename: String },
+    Load {
+        filename: String,
+        /// Format Stdout as text, markdown, json or foundry
+        #[arg(long = "format", short, value_enum, default_value_t = Format::Text)]
+        format: Format,
+    },
+
+    /// Buy items from a saved merchant at full price
+    Buy {
+        filename: String,
+        item: String,
+        #[arg(default_value_t = 1)]
+        quantity: u32,
+    },
+
+    /// Sell items to a saved merchant, for half price by default
+    Sell {
+        filename: String,
+        item: String,
+        #[arg(default_value_t = 1)]
+        quantity: u32,
+        /// Price paid for each item, e.g. "5 sp" (a bare number is in gp)
+        #[arg(long = "price", short, value_parser = parse_price)]
+        price: Option<i32>,
+    },
+
+    /// Simulate in-game time passing for a saved merchant
+    Restock {
+        filename: String,
+        /// Days since the players last visited
+        #[arg(long = "days", short, default_value_t = 7)]
+        days: u32,
+        /// Seed the restock to reproduce a previous one
+        #[arg(long = "seed")]
+        seed: Option<u64>,
+    },
+
+    /// Manage the equipment database
+    Db {
+        #[clap(subcommand)]
+        cmd: DbCommand,
+    },
+
+    /// Search the equipment database
+    Search {
+        /// Part of the item's name
+        name: Option<String>,
+        #[arg(long = "min-level")]
+        min_level: Option<i32>,
+        #[arg(long = "max-level")]
+        max_level: Option<i32>,
+        /// common, uncommon, rare or unique
+        #[arg(long = "rarity", short)]
+        rarity: Option<Rarity>,
+        /// Category as it appears on AoN, e.g. "Held Items"
+        #[arg(long = "category", short)]
+        category: Option<ItemCategory>,
+        /// Subcategory as it appears on AoN, e.g. "Alchemical Elixirs"
+        #[arg(long = "subcategory")]
+        subcategory: Option<String>,
+        /// A trait the item must have, e.g. "Invested"
+        #[arg(long = "trait", short = 't')]
+        has_trait: Option<String>,
+        /// Only show items with at least one of these traits (repeatable)
+        #[arg(long = "include-trait")]
+        include_traits: Vec<String>,
+        /// Hide items with this trait, e.g. "Tech" (repeatable)
+        #[arg(long = "exclude-trait")]
+        exclude_traits: Vec<String>,
+        /// Lowest price, e.g. "5 sp" (a bare number is in gp)
+        #[arg(long = "min-price", value_parser = parse_price)]
+        min_price: Option<i32>,
+        /// Highest price, e.g. "5 sp" (a bare number is in gp)
+        #[arg(long = "max-price", value_parser = parse_price)]
+        max_price: Option<i32>,
+        /// Bulk as it appears on AoN, e.g. "L" or "1"
+        #[arg(long = "bulk")]
+        bulk: Option<Bulk>,
+        /// Part of the item's source, e.g. "Player Core"
+        #[arg(long = "source")]
+        source: Option<String>,
+        /// Sort by name, level or price
+        #[arg(long = "sort", default_value = "name")]
+        sort: SortBy,
+        /// Sort in descending order
+        #[arg(long = "desc")]
+        desc: bool,
+        /// Results per page
+        #[arg(long = "limit", default_value_t = 25)]
+        limit: usize,
+        #[arg(long = "page", default_value_t = 1)]
+        page: usize,
+    },
+}
+
+#[derive(Debug, Parser)]
+enum DbCommand {
+    /// Add or replace items from a csv with the same columns as AoN's
+    /// Items with the same name and source as an existing item replace it
+    Import {
+        filename: PathBuf,
+        /// Tag the imported items with where they came from
+        #[arg(long = "source-tag", default_value = "homebrew")]
+        source_tag: String,
+    },
+    /// Reload the bundled items from the csv, keeping imported items
+    Rebuild,
+    /// List items without a price, which are never stocked
+    Prices {
+        /// Fill in or correct prices from a toml file of item names to prices,
+        /// e.g. `Club = "1 cp"`
+        #[arg(long)]
+        import: Option<PathBuf>,
+    },
+    /// List the subcategories in each category, as written in a profile's
+    /// `subcategory_weights`
+    Subcategories,
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum Format {
+    Text,
+    Markdown,
+    Json,
+    /// A Foundry VTT (pf2e) loot actor
+    Foundry,
+}
+
+fn print_merchant(merchant: &Merchant, format: Format) {
+    match format {
+        Format::Text => println!("{}", merchant),
+        Format::Markdown => println!("{}", merchant.markdown()),
+        Format::Json => println!("{}", merchant.json().unwrap()),
+        Format::Foundry => println!("{}", merchant.foundry().unwrap()),
+    }
+}
+
+fn print_settlement(settlement: &Settlement, format: Format) {
+    match format {
+        Format::Text => println!("{}", settlement),
+        Format::Markdown => println!("{}", settlement.markdown()),
+        Format::Json => println!("{}", settlement.json().unwrap()),
+        Format::Foundry => println!("{}", settlement.foundry().unwrap()),
+    }
+}
+
+/// Log the error and exit instead of panicking
+fn or_exit<T>(result: anyhow::Result<T>) -> T {
+    result.unwrap_or_else(|e| {
+        tracing::error!("An error occurred: {:#}", e);
+        std::process::exit(1);
+    })
+}
+
+/// Let the user know if the merchant couldn't spend their whole budget
+fn warn_incomplete(fill: &FillSummary) {
+    if !fill.is_complete() {
+        tracing::warn!(
+            "Stocked {} of {} before stopping because {}",
+            format_cp(fill.budget.spent()),
+            format_cp(fill.budget.target()),
+            fill.stop
+        );
+    }
+}
+
+/// The levels of item to stock, with the profile's window filling in any
+/// bound not given on the command line
+fn item_levels(
+    window: std::ops::RangeInclusive<i32>,
+    min: Option<i32>,
+    max: Option<i32>,
+) -> anyhow::Result<std::ops::RangeInclusive<i32>> {
+    let levels = min.unwrap_or(*window.start())..=max.unwrap_or(*window.end());
+    if levels.is_empty() {
+        anyhow::bail!(
+            "--min-level ({}) can't be above --max-level ({})",
+            levels.start(),
+            levels.end()
+        );
+    }
+    Ok(levels)
+}
+
+/// Parse a trait weight given on the command line, e.g. "Magical=0.5"
+fn parse_trait_weight(s: &str) -> anyhow::Result<(String, f32)> {
+    let (name, weight) = s
+        .split_once('=')
+        .context("Expected a trait and a multiplier, e.g. Magical=0.5")?;
+    Ok((name.trim().to_string(), parse_multiplier(weight)?))
+}
+
+fn parse_multiplier(s: &str) -> anyhow::Result<f32> {
+    let multiplier = s.trim().parse::<f32>()?;
+    if !multiplier.is_finite() || multiplier < 0.0 {
+        anyhow::bail!("Multiplier can't be negative");
+    }
+    Ok(multiplier)
+}
+
+/// Parse a price given on the command line into cp
+fn parse_price(s: &str) -> anyhow::Result<i32> {
+    let cp = match s.trim().parse::<i32>() {
+        Ok(gp) => gp.checked_mul(100).context("Price is too large")?,
+        Err(_) => Price::parse(s)?.context("Price must not be empty")?.as_cp(),
+    };
+    anyhow::ensure!(cp >= 0, "Price must not be negative");
+    Ok(cp)
 }
 
 #[tokio::main]
 async fn main() {
     tracing::debug!("Program Enter");
     #[cfg(debug_assertions)]
-    let env_filter = EnvFilter::builder().parse_lossy("sqlx=warn,merchant_gen_lib=debug");
+    let env_filter =
+        EnvFilter::builder().parse_lossy("sqlx=warn,merchant=debug,merchant_gen_lib=debug");
     #[cfg(debug_assertions)]
     tracing_subscriber::fmt()
         .with_max_level(tracing::Level::TRACE)
         .with_env_filter(env_filter)
+        .with_writer(std::io::stderr)
         .init();
     #[cfg(not(debug_assertions))]
     tracing_subscriber::fmt()
         .with_max_level(tracing::Level::WARN)
+        .with_writer(std::io::stderr)
         .init();
 
-    tracing::debug!("Initializing database");
-    let pool = merchant_gen_lib::database::init_db()
-        .await
-        .unwrap_or_else(|e| {
-            tracing::error!("An error occurred: {}", e);
-            std::process::exit(1);
-        });
-
     let cli = Cli::parse();
 
+    tracing::debug!("Initializing database");
+    let pool = or_exit(match &cli.db {
+        Some(path) => merchant_gen_lib::database::init_db_at(path).await,
+        None => merchant_gen_lib::database::init_db().await,
+    });
+
     match cli.subcmd {
         Subcommand::Generate {
             level,
+            wealth,
+            wealth_multiplier,
+            wealth_table,
+            min_level,
+            max_level,
+            include_traits,
+            require_traits,
+            exclude_traits,
+            trait_weights,
+            kind,
+            profile,
+            seed,
             save,
             markdown,
+            format,
         } => {
-            let mut merchant = Merchant::by_level(level);
-            merchant.generate_inventory(&pool).await.unwrap();
+            or_exit(wealth::validate_level(level));
+            let merchant = match (wealth, wealth_table) {
+                (Some(gp), _) => Merchant::from_gp(gp, level),
+                (None, Some(path)) => {
+                    Merchant::by_level_in(level, &or_exit(WealthTable::from_file(path)))
+                }
+                (None, None) => Merchant::by_level(level),
+            };
+            let mut merchant = merchant
+                .with_wealth_multiplier(wealth_multiplier)
+                .with_kind(kind);
+            if let Some(profile) = profile {
+                let profile = or_exit(MerchantProfile::from_file(profile));
+                merchant = merchant.with_profile(profile);
+            }
+            if min_level.is_some() || max_level.is_some() {
+                let window = merchant.profile().level_window(level);
+                merchant =
+                    merchant.with_item_levels(or_exit(item_levels(window, min_level, max_level)));
+            }
+            if !include_traits.is_empty() {
+                merchant = merchant.with_included_traits(include_traits);
+            }
+            if !require_traits.is_empty() {
+                merchant = merchant.with_required_traits(require_traits);
+            }
+            if !exclude_traits.is_empty() {
+                merchant = merchant.with_excluded_traits(exclude_traits);
+            }
+            for (trait_name, weight) in trait_weights {
+                merchant = merchant.with_trait_weight(trait_name, weight);
+            }
+            let fill = or_exit(match seed {
+                Some(seed) => merchant.generate_inventory_with_seed(&pool, seed).await,
+                None => merchant.generate_inventory(&pool).await,
+            });
+            warn_incomplete(&fill);
 
             if save {
                 merchant.save().unwrap();
             }
 
             if markdown {
-                println!("{}", merchant.markdown());
+                print_merchant(&merchant, Format::Markdown);
             } else {
-                println!("{}", merchant);
+                print_merchant(&merchant, format);
             }
         }
-        Subcommand::Load { filename } => {
-            let merchant = Merchant::read_from_file(filename);
-            println!("{}", merchant);
+        Subcommand::Town {
+            level,
+            size,
+            seed,
+            save,
+            format,
+        } => {
+            let mut settlement = or_exit(Settlement::new(level, size));
+            or_exit(match seed {
+                Some(seed) => settlement.generate_with_seed(&pool, seed).await,
+                None => settlement.generate(&pool).await,
+            });
+
+            if save {
+                or_exit(settlement.save());
+            }
+
+            print_settlement(&settlement, format);
+        }
+        Subcommand::Load { filename, format } => {
+            let merchant = or_exit(Merchant::load(&filename));
+            print_merchant(&merchant, format);
+        }
+        Subcommand::Buy {
+            filename,
+            item,
+            quantity,
+        } => {
+            let mut merchant = or_exit(Merchant::load(&filename));
+            let transaction = or_exit(merchant.buy(&item, quantity));
+            or_exit(merchant.save_to(&filename));
+            println!("{}", transaction);
+            println!("Merchant wealth: {}", format_cp(merchant.wealth()));
+        }
+        Subcommand::Sell {
+            filename,
+            item,
+            quantity,
+            price,
+        } => {
+            let mut merchant = or_exit(Merchant::load(&filename));
+            let item = match merchant.inventory().get(&item) {
+                Some(stack) => stack.item.clone(),
+                None => or_exit(
+                    merchant_gen_lib::database::get_by_name(&pool, &item)
+                        .await
+                        .and_then(|i| i.with_context(|| format!("Unknown item: {}", item))),
+                ),
+            };
+            let transaction = or_exit(merchant.sell(item, quantity, price));
+            or_exit(merchant.save_to(&filename));
+            println!("{}", transaction);
+            println!("Merchant wealth: {}", format_cp(merchant.wealth()));
+        }
+        Subcommand::Restock {
+            filename,
+            days,
+            seed,
+        } => {
+            let mut merchant = or_exit(Merchant::load(&filename));
+            let summary = or_exit(match seed {
+                Some(seed) => merchant.restock_with_seed(&pool, days, seed).await,
+                None => merchant.restock(&pool, days).await,
+            });
+            warn_incomplete(&summary.fill);
+            or_exit(merchant.save_to(&filename));
+            println!(
+                "{} items sold and {} items stocked over {} days",
+                summary.sold, summary.added, days
+            );
+            println!("Merchant wealth: {}", format_cp(merchant.wealth()));
+        }
+        Subcommand::Db { cmd } => match cmd {
+            DbCommand::Import {
+                filename,
+                source_tag,
+            } => {
+                let summary = or_exit(
+                    merchant_gen_lib::database::import_csv(&pool, &filename, &source_tag).await,
+                );
+                println!(
+                    "Imported {} new items and replaced {} existing items",
+                    summary.inserted, summary.updated
+                );
+            }
+            DbCommand::Rebuild => {
+                let count = or_exit(merchant_gen_lib::database::reseed(&pool).await);
+                println!("Loaded {} bundled items", count);
+            }
+            DbCommand::Prices { import } => {
+                if let Some(filename) = import {
+                    let count =
+                        or_exit(merchant_gen_lib::database::import_prices(&pool, &filename).await);
+                    println!("Imported {} prices", count);
+                } else {
+                    let items = or_exit(merchant_gen_lib::database::get_unpriced(&pool).await);
+                    for item in items.iter() {
+                        println!(
+                            "{} (level {}, {}) - {}",
+                            item.name, item.level, item.item_category, item.source
+                        );
+                    }
+                    println!("\n{} items without a price", items.len());
+                }
+            }
+            DbCommand::Subcategories => {
+                let catalog = or_exit(ItemCatalog::load(&pool).await);
+                for category in catalog.categories() {
+                    let subcategories = catalog.subcategories(category);
+                    println!("{}", category);
+                    for (subcategory, count) in subcategories {
+                        let subcategory = if subcategory.is_empty() {
+                            "(none)"
+                        } else {
+                            subcategory
+                        };
+                        println!("    {} ({} items)", subcategory, count);
+                    }
+                }
+            }
+        },
+        Subcommand::Search {
+            name,
+            min_level,
+            max_level,
+            rarity,
+            category,
+            subcategory,
+            has_trait,
+            include_traits,
+            exclude_traits,
+            min_price,
+            max_price,
+            bulk,
+            source,
+            sort,
+            desc,
+            limit,
+            page,
+        } => {
+            let offset = page.saturating_sub(1).saturating_mul(limit);
+            let query = ItemQuery {
+                name,
+                min_level,
+                max_level,
+                rarity,
+                category,
+                subcategory,
+                has_trait,
+                any_traits: include_traits,
+                excluded_traits: exclude_traits,
+                min_price,
+                max_price,
+                bulk,
+                source,
+                sort_by: sort,
+                descending: desc,
+                limit: Some(limit),
+                offset,
+            };
+            let results = or_exit(merchant_gen_lib::database::search(&pool, &query).await);
+
+            for item in results.items.iter() {
+                let mut category = item.item_category.to_string();
+                if !item.item_subcategory.is_empty() {
+                    category = format!("{} / {}", category, item.item_subcategory);
+                }
+                let price = match &item.price {
+                    Some(price) => price.to_string(),
+                    None => "no price".to_string(),
+                };
+                println!(
+                    "{} (level {}, {}, {}) - {}",
+                    item.name, item.level, item.rarity, category, price
+                );
+            }
+            if results.total == 0 {
+                println!("No items found");
+            } else if results.items.is_empty() {
+                println!("No items on page {} ({} total)", page, results.total);
+            } else {
+                println!(
+                    "\nShowing {}-{} of {}",
+                    offset + 1,
+                    offset + results.items.len(),
+                    results.total
+                );
+            }
         }
     }
 }
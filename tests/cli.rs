@@ -0,0 +1,52 @@
+use std::{path::PathBuf, process::Command};
+
+/// A database path of its own for each test, so tests can run in parallel
+fn db_path(name: &str) -> PathBuf {
+    let path =
+        std::env::temp_dir().join(format!("merchant-cli-{}-{}.db", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+fn merchant(db: &PathBuf, args: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_merchant"))
+        .arg("--db")
+        .arg(db)
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn json_output_is_only_json() {
+    let db = db_path("json");
+    let output = merchant(&db, &["gen", "3", "--seed", "1", "--format", "json"]);
+    assert!(output.status.success(), "{:?}", output);
+
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["merchant"]["level"], 3);
+    let _ = std::fs::remove_file(db);
+}
+
+#[test]
+fn wealth_out_of_range_is_rejected() {
+    let db = db_path("wealth");
+    for wealth in ["30000000", "-5"] {
+        let output = merchant(&db, &["gen", "5", "--wealth", wealth]);
+        assert_eq!(output.status.code(), Some(2), "{:?}", output);
+        assert!(!String::from_utf8_lossy(&output.stderr).contains("panicked"));
+    }
+    let _ = std::fs::remove_file(db);
+}
+
+#[test]
+fn huge_search_arguments_are_handled() {
+    let db = db_path("search");
+    let output = merchant(&db, &["search", "--max-price", "30000000"]);
+    assert_eq!(output.status.code(), Some(2), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("too large"));
+
+    let output = merchant(&db, &["search", "--page", "4294967295", "--limit", "2"]);
+    assert!(output.status.success(), "{:?}", output);
+    let _ = std::fs::remove_file(db);
+}